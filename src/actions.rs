@@ -0,0 +1,197 @@
+use termion::event::Key;
+use crate::config::Config;
+use crate::mpd::{quote, MpdClient, MpdError, Response};
+
+/// MPD transport actions. These work from any mode that is not taking text
+/// input, and do not need a widget to have focus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    TogglePause,
+    Stop,
+    Next,
+    Previous,
+    SeekForward,
+    SeekBackward,
+    VolumeUp,
+    VolumeDown,
+    ToggleRepeat,
+    ToggleRandom,
+    ToggleSingle,
+    ToggleConsume,
+    CrossfadeUp,
+    CrossfadeDown,
+    UpdateDatabase,
+    RescanDatabase,
+}
+
+pub const PLAYBACK_KEYS: &[(Key, Action)] = &[
+    (Key::Char('p'), Action::TogglePause),
+    (Key::Char('s'), Action::Stop),
+    (Key::Char('>'), Action::Next),
+    (Key::Char('<'), Action::Previous),
+    (Key::Char(']'), Action::SeekForward),
+    (Key::Char('['), Action::SeekBackward),
+    (Key::Char('+'), Action::VolumeUp),
+    (Key::Char('='), Action::VolumeUp),
+    (Key::Char('-'), Action::VolumeDown),
+    (Key::Char('R'), Action::ToggleRepeat),
+    (Key::Char('Z'), Action::ToggleRandom),
+    (Key::Char('y'), Action::ToggleSingle),
+    (Key::Char('C'), Action::ToggleConsume),
+    (Key::Char('}'), Action::CrossfadeUp),
+    (Key::Char('{'), Action::CrossfadeDown),
+    (Key::Char('u'), Action::UpdateDatabase),
+    (Key::Char('U'), Action::RescanDatabase),
+];
+
+pub fn playback_action(key: Key) -> Option<Action> {
+    PLAYBACK_KEYS.iter().find(|(k, _)| *k == key).map(|(_, action)| *action)
+}
+
+impl Action {
+    /// Finds an action by its `id`.
+    pub fn from_id(id: &str) -> Option<Action> {
+        PLAYBACK_KEYS.iter().map(|(_, action)| *action).find(|action| action.id() == id)
+    }
+
+    /// The name scripts use for the action.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Action::TogglePause => "toggle-pause",
+            Action::Stop => "stop",
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::SeekForward => "seek-forward",
+            Action::SeekBackward => "seek-backward",
+            Action::VolumeUp => "volume-up",
+            Action::VolumeDown => "volume-down",
+            Action::ToggleRepeat => "toggle-repeat",
+            Action::ToggleRandom => "toggle-random",
+            Action::ToggleSingle => "toggle-single",
+            Action::ToggleConsume => "toggle-consume",
+            Action::CrossfadeUp => "crossfade-up",
+            Action::CrossfadeDown => "crossfade-down",
+            Action::UpdateDatabase => "update-database",
+            Action::RescanDatabase => "rescan-database",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::TogglePause => "Play/pause",
+            Action::Stop => "Stop",
+            Action::Next => "Next song",
+            Action::Previous => "Previous song",
+            Action::SeekForward => "Seek forward",
+            Action::SeekBackward => "Seek backward",
+            Action::VolumeUp => "Volume up",
+            Action::VolumeDown => "Volume down",
+            Action::ToggleRepeat => "Toggle repeat",
+            Action::ToggleRandom => "Toggle random",
+            Action::ToggleSingle => "Toggle single",
+            Action::ToggleConsume => "Toggle consume",
+            Action::CrossfadeUp => "Crossfade up",
+            Action::CrossfadeDown => "Crossfade down",
+            Action::UpdateDatabase => "Update database",
+            Action::RescanDatabase => "Rescan database",
+        }
+    }
+
+    /// Runs the action and returns a message for the bottom bar.
+    pub fn run(&self, client: &mut MpdClient, config: &Config) -> Result<String, MpdError> {
+        match self {
+            Action::TogglePause => {
+                let status = client.status()?;
+                match status.get("state") {
+                    Some("play") => {
+                        client.command("pause 1")?;
+                        Ok(String::from("Paused"))
+                    }
+                    Some("pause") => {
+                        client.command("pause 0")?;
+                        Ok(String::from("Playing"))
+                    }
+                    _ => {
+                        client.command("play")?;
+                        Ok(String::from("Playing"))
+                    }
+                }
+            }
+            Action::Stop => {
+                client.command("stop")?;
+                Ok(String::from("Stopped"))
+            }
+            Action::Next => {
+                client.command("next")?;
+                Ok(String::from("Next song"))
+            }
+            Action::Previous => {
+                client.command("previous")?;
+                Ok(String::from("Previous song"))
+            }
+            Action::SeekForward => {
+                client.command(&format!("seekcur +{}", config.seek_step))?;
+                Ok(format!("Seek +{}s", config.seek_step))
+            }
+            Action::SeekBackward => {
+                client.command(&format!("seekcur -{}", config.seek_step))?;
+                Ok(format!("Seek -{}s", config.seek_step))
+            }
+            Action::VolumeUp => change_volume(client, config.volume_step as i64),
+            Action::VolumeDown => change_volume(client, -(config.volume_step as i64)),
+            Action::ToggleRepeat => toggle(client, "repeat", "Repeat"),
+            Action::ToggleRandom => toggle(client, "random", "Random"),
+            Action::ToggleSingle => toggle(client, "single", "Single"),
+            Action::ToggleConsume => toggle(client, "consume", "Consume"),
+            Action::CrossfadeUp => change_crossfade(client, config.crossfade_step as i64),
+            Action::CrossfadeDown => change_crossfade(client, -(config.crossfade_step as i64)),
+            Action::UpdateDatabase => update_database(client, false, None),
+            Action::RescanDatabase => update_database(client, true, None),
+        }
+    }
+}
+
+fn is_on(status: &Response, field: &str) -> bool {
+    // single and consume may also be "oneshot", which counts as on
+    !matches!(status.get(field), None | Some("0"))
+}
+
+fn toggle(client: &mut MpdClient, field: &str, name: &str) -> Result<String, MpdError> {
+    let on = !is_on(&client.status()?, field);
+    client.command(&format!("{} {}", field, on as u8))?;
+    Ok(format!("{} {}", name, if on { "on" } else { "off" }))
+}
+
+fn change_volume(client: &mut MpdClient, delta: i64) -> Result<String, MpdError> {
+    let volume = client.status()?.parse::<i64>("volume").unwrap_or(-1);
+    if volume < 0 {
+        return Ok(String::from("No mixer available"));
+    }
+    let volume = (volume + delta).clamp(0, 100);
+    client.command(&format!("setvol {}", volume))?;
+    Ok(format!("Volume {}%", volume))
+}
+
+fn change_crossfade(client: &mut MpdClient, delta: i64) -> Result<String, MpdError> {
+    // xfade is left out of the status when crossfade is off
+    let crossfade = client.status()?.parse::<i64>("xfade").unwrap_or(0);
+    let crossfade = (crossfade + delta).max(0);
+    client.command(&format!("crossfade {}", crossfade))?;
+    Ok(format!("Crossfade {}s", crossfade))
+}
+
+/// Starts an `update`, or a `rescan` which also rereads unchanged files, of
+/// `directory` or the whole database. The job's progress is reported through
+/// the `update` idle subsystem.
+pub fn update_database(client: &mut MpdClient, rescan: bool, directory: Option<&str>) -> Result<String, MpdError> {
+    let command = if rescan { "rescan" } else { "update" };
+    let response = match directory {
+        Some(directory) => client.command(&format!("{} {}", command, quote(directory)))?,
+        None => client.command(command)?,
+    };
+    let job = response.get("updating_db").unwrap_or("?");
+    match directory {
+        Some(directory) => Ok(format!("Updating {} (job {})", directory, job)),
+        None => Ok(format!("Updating database (job {})", job)),
+    }
+}
@@ -0,0 +1,248 @@
+use termion::event::Key;
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use unicode_width::UnicodeWidthChar;
+
+/// Turns a line of program output into spans, applying SGR color and style
+/// codes on top of `base`. Other escape sequences are dropped, and a
+/// carriage return starts the line over, as progress bars expect. `style`
+/// carries the SGR state from one line to the next.
+pub fn parse_line(line: &str, base: Style, style: &mut Style) -> Spans<'static> {
+    let line = line.rsplit('\r').next().unwrap_or("");
+    let mut spans = Vec::new();
+    let mut text = String::new();
+    // display column in the whole line, for tab stops
+    let mut column = 0;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                let sequence = match chars.next() {
+                    Some('[') => {
+                        let mut params = String::new();
+                        let mut last = None;
+                        for c in chars.by_ref() {
+                            if ('\x40'..='\x7e').contains(&c) {
+                                last = Some(c);
+                                break;
+                            }
+                            params.push(c);
+                        }
+                        last.map(|last| (params, last))
+                    }
+                    // OSC, such as a window title, ends with BEL or ST
+                    Some(']') => {
+                        while let Some(c) = chars.next() {
+                            if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                                break;
+                            }
+                        }
+                        None
+                    }
+                    _ => None,
+                };
+                if let Some((params, 'm')) = sequence {
+                    if !text.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut text), base.patch(*style)));
+                    }
+                    apply_sgr(&params, style);
+                }
+            }
+            '\t' => {
+                let spaces = 8 - column % 8;
+                text.push_str(&" ".repeat(spaces));
+                column += spaces;
+            }
+            c if c.is_control() => {}
+            c => {
+                text.push(c);
+                column += c.width().unwrap_or(0);
+            }
+        }
+    }
+    if !text.is_empty() {
+        spans.push(Span::styled(text, base.patch(*style)));
+    }
+    Spans::from(spans)
+}
+
+/// Applies the `;` separated parameters of an SGR sequence.
+fn apply_sgr(params: &str, style: &mut Style) {
+    let mut codes = params.split([';', ':']).map(|code| code.parse::<u16>().unwrap_or(0));
+    // an empty sequence resets, like 0
+    if params.is_empty() {
+        *style = Style::default();
+        return;
+    }
+    while let Some(code) = codes.next() {
+        *style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            5 | 6 => style.add_modifier(Modifier::SLOW_BLINK),
+            7 => style.add_modifier(Modifier::REVERSED),
+            8 => style.add_modifier(Modifier::HIDDEN),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            25 => style.remove_modifier(Modifier::SLOW_BLINK),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            28 => style.remove_modifier(Modifier::HIDDEN),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(indexed((code - 30) as u8)),
+            38 => match extended(&mut codes) {
+                Some(color) => style.fg(color),
+                None => *style,
+            },
+            39 => Style { fg: None, ..*style },
+            40..=47 => style.bg(indexed((code - 40) as u8)),
+            48 => match extended(&mut codes) {
+                Some(color) => style.bg(color),
+                None => *style,
+            },
+            49 => Style { bg: None, ..*style },
+            90..=97 => style.fg(indexed((code - 90 + 8) as u8)),
+            100..=107 => style.bg(indexed((code - 100 + 8) as u8)),
+            _ => *style,
+        };
+    }
+}
+
+/// Reads the rest of a `38` or `48` code: `5;n` for the 256 color palette,
+/// `2;r;g;b` for true color.
+fn extended(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    match codes.next()? {
+        5 => Some(indexed(codes.next()? as u8)),
+        2 => Some(Color::Rgb(codes.next()? as u8, codes.next()? as u8, codes.next()? as u8)),
+        _ => None,
+    }
+}
+
+/// A palette color, using the named colors for the first 16 so they follow
+/// the terminal's theme.
+pub fn indexed(index: u8) -> Color {
+    match index {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::Gray,
+        8 => Color::DarkGray,
+        9 => Color::LightRed,
+        10 => Color::LightGreen,
+        11 => Color::LightYellow,
+        12 => Color::LightBlue,
+        13 => Color::LightMagenta,
+        14 => Color::LightCyan,
+        15 => Color::White,
+        index => Color::Indexed(index),
+    }
+}
+
+/// The bytes a terminal sends for `key`, for passing keys on to a program.
+pub fn key_bytes(key: Key) -> Option<Vec<u8>> {
+    Some(match key {
+        Key::Char(c) => c.to_string().into_bytes(),
+        Key::Ctrl(c) => vec![(c as u8) & 0x1f],
+        Key::Alt(c) => {
+            let mut bytes = vec![0x1b];
+            bytes.extend(c.to_string().into_bytes());
+            bytes
+        }
+        Key::Backspace => vec![0x7f],
+        Key::Esc => vec![0x1b],
+        Key::Up => b"\x1b[A".to_vec(),
+        Key::Down => b"\x1b[B".to_vec(),
+        Key::Right => b"\x1b[C".to_vec(),
+        Key::Left => b"\x1b[D".to_vec(),
+        Key::Home => b"\x1b[H".to_vec(),
+        Key::End => b"\x1b[F".to_vec(),
+        Key::Insert => b"\x1b[2~".to_vec(),
+        Key::Delete => b"\x1b[3~".to_vec(),
+        Key::PageUp => b"\x1b[5~".to_vec(),
+        Key::PageDown => b"\x1b[6~".to_vec(),
+        Key::BackTab => b"\x1b[Z".to_vec(),
+        Key::F(n @ 1..=4) => format!("\x1bO{}", (b'P' + n - 1) as char).into_bytes(),
+        Key::F(n @ 5..=12) => {
+            const CODES: [u8; 8] = [15, 17, 18, 19, 20, 21, 23, 24];
+            format!("\x1b[{}~", CODES[n as usize - 5]).into_bytes()
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text and style of each span.
+    fn spans(line: &str, style: &mut Style) -> Vec<(String, Style)> {
+        parse_line(line, Style::default(), style).0.into_iter()
+            .map(|span| (span.content.into_owned(), span.style))
+            .collect()
+    }
+
+    #[test]
+    fn styles_carry_over_to_the_next_line() {
+        let mut style = Style::default();
+        let red = Style::default().fg(Color::Red);
+        assert_eq!(spans("plain \x1b[31mred", &mut style), [(String::from("plain "), Style::default()), (String::from("red"), red)]);
+        assert_eq!(spans("still red", &mut style), [(String::from("still red"), red)]);
+        assert_eq!(spans("\x1b[1mbold too\x1b[0m", &mut style), [(String::from("bold too"), red.add_modifier(Modifier::BOLD))]);
+        assert_eq!(style, Style::default());
+    }
+
+    #[test]
+    fn palette_and_true_colors() {
+        let mut style = Style::default();
+        let parsed = spans("\x1b[38;5;33ma\x1b[48;5;4mb\x1b[38;2;1;2;3mc\x1b[48:2:250:128:0md", &mut style);
+        let styles = parsed.iter().map(|(_, style)| *style).collect::<Vec<_>>();
+        assert_eq!(styles, [
+            Style::default().fg(Color::Indexed(33)),
+            Style::default().fg(Color::Indexed(33)).bg(Color::Blue),
+            Style::default().fg(Color::Rgb(1, 2, 3)).bg(Color::Blue),
+            Style::default().fg(Color::Rgb(1, 2, 3)).bg(Color::Rgb(250, 128, 0)),
+        ]);
+        // a color cut short is ignored, and the codes after it still apply
+        let mut style = Style::default();
+        spans("\x1b[38;5m\x1b[92mx", &mut style);
+        assert_eq!(style, Style::default().fg(Color::LightGreen));
+    }
+
+    #[test]
+    fn resets() {
+        let base = Style::default().fg(Color::Gray);
+        let mut style = Style::default();
+        let parsed = parse_line("\x1b[1;4;31ma\x1b[mb\x1b[7mc\x1b[27;39md", base, &mut style);
+        let styles = parsed.0.iter().map(|span| span.style).collect::<Vec<_>>();
+        assert_eq!(styles, [
+            base.fg(Color::Red).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            base,
+            base.add_modifier(Modifier::REVERSED),
+            base.remove_modifier(Modifier::REVERSED),
+        ]);
+    }
+
+    #[test]
+    fn other_sequences_are_dropped() {
+        let mut style = Style::default();
+        let parsed = spans("\x1b]0;title\x07\x1b[2Kdone\rredrawn\ta", &mut style);
+        assert_eq!(parsed, [(String::from("redrawn a"), Style::default())]);
+    }
+
+    #[test]
+    fn tabs_stop_at_columns_of_the_whole_line() {
+        let mut style = Style::default();
+        let parsed = spans("\x1b[31mab\x1b[0m\tc", &mut style);
+        assert_eq!(parsed[1], (String::from("      c"), Style::default()));
+        // wide characters take two columns
+        let parsed = spans("日本\tx", &mut style);
+        assert_eq!(parsed, [(String::from("日本    x"), Style::default())]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+use clap::{Parser, Subcommand};
+use crate::actions::PLAYBACK_KEYS;
+use crate::config::{self, Config};
+use crate::containers::Container;
+use crate::keymap;
+use crate::layout;
+use crate::registry::Registry;
+use crate::script::{self, KeyBindings};
+
+/// Command-line flags. Those that name a server or a layout take the place
+/// of the config's settings, including those of its starting profile.
+#[derive(Parser)]
+#[command(version, about = "A terminal MPD client built from panes")]
+pub struct Cli {
+    /// Layout file to load, and to save to with 'w'.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub layout: Option<PathBuf>,
+    /// Config file to read instead of config.json in the config directory.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// MPD host name.
+    #[arg(long)]
+    pub host: Option<String>,
+    /// MPD port.
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// Unix socket of MPD, instead of a host and port.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["host", "port"])]
+    pub socket: Option<String>,
+    /// JSON file with focus styles to use in place of the config's
+    /// `focus_style`.
+    #[arg(long, value_name = "FILE")]
+    pub theme: Option<PathBuf>,
+    /// Leaves mouse reporting off, so the terminal can select text.
+    #[arg(long)]
+    pub no_mouse: bool,
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+/// Commands that print something and exit without starting the interface.
+#[derive(Subcommand)]
+pub enum CliCommand {
+    /// Checks that layout files load, exiting with 1 if any do not. Without
+    /// files, checks the layout the app would load. Nothing in the layouts is
+    /// started, so commands and scripts in them do not run.
+    ValidateLayout {
+        files: Vec<PathBuf>,
+    },
+    /// Prints the layout used when there is no layout file.
+    DumpDefaultLayout,
+    /// Prints the keys of each mode, and those bound by scripts.
+    PrintKeymap,
+}
+
+impl Cli {
+    /// Loads the config, applying the flags, and picks the layout file.
+    /// Returns why the config could not be used as given, if it could not.
+    pub fn load_config(&self) -> (Config, PathBuf, Option<String>) {
+        let config_path = self.config.clone().unwrap_or_else(config::default_path);
+        let mut layout_path = layout::default_path();
+        let mut error = None;
+        let mut config = match Config::load(&config_path) {
+            Ok(config) => config,
+            Err(err) => {
+                error = Some(format!("Could not load {}: {}", config_path.display(), err));
+                Config::default()
+            }
+        };
+        if let Some(name) = config.profile.clone() {
+            match config.find_profile(&name).cloned() {
+                Some(profile) => {
+                    config.use_profile(&profile);
+                    layout_path = profile.layout_path();
+                }
+                None => error = Some(format!("No profile named {}", name)),
+            }
+        }
+        if let Some(host) = &self.host {
+            config.host = host.clone();
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(socket) = &self.socket {
+            config.host = config::expand_home(socket).to_string_lossy().into_owned();
+        }
+        if let Some(layout) = &self.layout {
+            layout_path = layout.clone();
+        }
+        (config, layout_path, error)
+    }
+}
+
+/// Runs a command, returning the exit code.
+pub fn run(command: &CliCommand, layout_path: &Path, registry: &Registry, default_layout: fn() -> Box<dyn Container>) -> i32 {
+    match command {
+        CliCommand::ValidateLayout { files } => {
+            let files = if files.is_empty() {
+                vec![layout_path.to_path_buf()]
+            } else {
+                files.clone()
+            };
+            let mut code = 0;
+            for file in files {
+                match layout::check(&file, registry) {
+                    Ok(_) => println!("{}: ok", file.display()),
+                    Err(err) => {
+                        eprintln!("{}: {}", file.display(), err);
+                        code = 1;
+                    }
+                }
+            }
+            code
+        }
+        CliCommand::DumpDefaultLayout => match serde_json::to_string_pretty(&default_layout().to_value()) {
+            Ok(json) => {
+                println!("{}", json);
+                0
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                1
+            }
+        },
+        CliCommand::PrintKeymap => {
+            print_keymap();
+            0
+        }
+    }
+}
+
+fn print_keymap() {
+    for mode in keymap::MODES {
+        println!("{} mode", mode.name);
+        for (key, description) in mode.keys {
+            println!("  {:<20}{}", key, description);
+        }
+        println!();
+    }
+    println!("Playback, in every mode except while typing");
+    for (key, action) in PLAYBACK_KEYS {
+        let key = script::key_name(*key).unwrap_or_default();
+        println!("  {:<20}{} ({})", key, action.name(), action.id());
+    }
+    let (bindings, errors) = KeyBindings::load();
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    let bound = bindings.bound().collect::<Vec<_>>();
+    if !bound.is_empty() {
+        println!();
+        println!("Scripts, in Normal mode");
+        for (key, script, function) in bound {
+            println!("  {:<20}{}::{}", key, script, function);
+        }
+    }
+}
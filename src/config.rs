@@ -0,0 +1,178 @@
+use std::{env, fs, io};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::containers::FocusStyle;
+use crate::control;
+use crate::mpd::{self, MpdClient, MpdError};
+
+/// `$XDG_CONFIG_HOME/mpd-thing-rs`, falling back to `~/.config/mpd-thing-rs`.
+pub fn config_dir() -> PathBuf {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config"),
+    };
+    base.join("mpd-thing-rs")
+}
+
+pub fn default_path() -> PathBuf {
+    config_dir().join("config.json")
+}
+
+/// Settings read from `config.json`. Missing fields take their defaults, so an
+/// empty file is a valid config.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Host name, or the path of a Unix socket.
+    pub host: String,
+    pub port: u16,
+    /// Seconds to seek per key press.
+    pub seek_step: u32,
+    /// Percent to change the volume per key press.
+    pub volume_step: u32,
+    /// Seconds to change the crossfade per key press.
+    pub crossfade_step: u32,
+    /// MPD's `music_directory`, for files kept next to the songs such as
+    /// lyrics. `~/` is expanded.
+    pub music_directory: Option<String>,
+    /// Another directory searched for lyrics, as `Artist - Title.lrc` or
+    /// the song's file name with an `.lrc` or `.txt` extension.
+    pub lyrics_dir: Option<String>,
+    pub password: Option<String>,
+    /// The partition to control. Panes can pick another one.
+    pub partition: Option<String>,
+    /// Milliseconds to wait for MPD to answer a command before dropping the
+    /// connection. 0 waits forever.
+    pub timeout_ms: u64,
+    /// Servers that can be switched between at runtime.
+    pub profiles: Vec<Profile>,
+    /// The profile to start with. Without one the settings above are used.
+    pub profile: Option<String>,
+    /// Path of the socket scripts control the app through. Defaults to
+    /// `mpd-thing-rs.sock` in `$XDG_RUNTIME_DIR`. An empty path turns it off.
+    pub control_socket: Option<String>,
+    /// Styles of the focused pane, the containers around it and the pane
+    /// Select mode acts on, as `focused`, `parent` and `candidate`.
+    pub focus_style: FocusStyle,
+}
+
+/// A named MPD server. Choosing it replaces the connection settings of the
+/// config.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    /// Host name, or the path of a Unix socket.
+    pub host: String,
+    pub port: u16,
+    pub password: Option<String>,
+    pub music_directory: Option<String>,
+    pub partition: Option<String>,
+    /// Layout file for this profile. Defaults to `layouts/<name>.json` in
+    /// the config directory.
+    pub layout: Option<String>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            name: String::new(),
+            host: String::from("localhost"),
+            port: 6600,
+            password: None,
+            music_directory: None,
+            partition: None,
+            layout: None,
+        }
+    }
+}
+
+impl Profile {
+    pub fn layout_path(&self) -> PathBuf {
+        match &self.layout {
+            Some(layout) => expand_home(layout),
+            None => config_dir().join("layouts").join(format!("{}.json", self.name)),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            host: env::var("MPD_HOST").unwrap_or_else(|_| String::from("localhost")),
+            port: env::var("MPD_PORT").ok().and_then(|port| port.parse().ok()).unwrap_or(6600),
+            seek_step: 5,
+            volume_step: 5,
+            crossfade_step: 1,
+            music_directory: None,
+            lyrics_dir: None,
+            password: None,
+            partition: None,
+            timeout_ms: 5000,
+            profiles: Vec::new(),
+            profile: None,
+            control_socket: None,
+            focus_style: FocusStyle::default(),
+        }
+    }
+}
+
+/// Expands a leading `~/` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+impl Config {
+    /// Reads the config file, using the defaults if it does not exist.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|err| format!("invalid config: {}", err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    pub fn find_profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Switches the connection settings to those of `profile`.
+    pub fn use_profile(&mut self, profile: &Profile) {
+        self.host = profile.host.clone();
+        self.port = profile.port;
+        self.password = profile.password.clone();
+        self.music_directory = profile.music_directory.clone();
+        self.partition = profile.partition.clone();
+        self.profile = Some(profile.name.clone());
+    }
+
+    /// Connects to the configured server, sends the password, if any, and
+    /// enters the configured partition.
+    pub fn connect(&self) -> Result<MpdClient, MpdError> {
+        let timeout = Some(Duration::from_millis(self.timeout_ms)).filter(|timeout| !timeout.is_zero());
+        let mut client = MpdClient::connect(&self.host, self.port, timeout)?;
+        if let Some(password) = &self.password {
+            client.password(password)?;
+        }
+        client.enter_partition(self.partition_name())?;
+        Ok(client)
+    }
+
+    /// Where the control socket listens, or `None` if it is turned off.
+    pub fn control_socket_path(&self) -> Option<PathBuf> {
+        match self.control_socket.as_deref() {
+            Some("") => None,
+            Some(path) => Some(expand_home(path)),
+            None => Some(control::default_path()),
+        }
+    }
+
+    /// The partition the config controls.
+    pub fn partition_name(&self) -> &str {
+        self.partition.as_deref().unwrap_or(mpd::DEFAULT_PARTITION)
+    }
+}
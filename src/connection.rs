@@ -0,0 +1,151 @@
+use std::io;
+use std::time::{Duration, Instant};
+use crate::config::Config;
+use crate::mpd::{MpdClient, MpdError};
+
+const MIN_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Delays between reconnect attempts, doubling after every failure up to
+/// `MAX_DELAY`.
+pub struct Backoff {
+    delay: Duration,
+    next: Instant,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            delay: MIN_DELAY,
+            next: Instant::now(),
+        }
+    }
+}
+
+impl Backoff {
+    /// Whether the next attempt may be made now.
+    pub fn ready(&self) -> bool {
+        Instant::now() >= self.next
+    }
+
+    /// Time left until the next attempt.
+    pub fn remaining(&self) -> Duration {
+        self.next.saturating_duration_since(Instant::now())
+    }
+
+    pub fn failed(&mut self) {
+        self.next = Instant::now() + self.delay;
+        self.delay = (self.delay * 2).min(MAX_DELAY);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Backoff::default();
+    }
+}
+
+/// The main thread's connection to MPD. It is made on first use and again
+/// after it is lost, no sooner than the backoff allows.
+#[derive(Default)]
+pub struct Connection {
+    client: Option<MpdClient>,
+    backoff: Backoff,
+    /// Why the last connection failed or was lost.
+    error: Option<String>,
+}
+
+impl Connection {
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// The connected client, connecting first if needed.
+    pub fn client(&mut self, config: &Config) -> Result<&mut MpdClient, MpdError> {
+        if self.client.is_none() {
+            if !self.backoff.ready() {
+                let message = format!("not connected, retrying in {}s", self.backoff.remaining().as_secs() + 1);
+                return Err(MpdError::Io(io::Error::new(io::ErrorKind::NotConnected, message)));
+            }
+            match config.connect() {
+                Ok(client) => {
+                    self.backoff.reset();
+                    self.error = None;
+                    self.client = Some(client);
+                }
+                Err(err) => {
+                    self.backoff.failed();
+                    self.error = Some(err.to_string());
+                    return Err(err);
+                }
+            }
+        }
+        Ok(self.client.as_mut().unwrap())
+    }
+
+    /// Passes `result` through, dropping the connection if it failed with an
+    /// I/O error, since the protocol state is unknown after one.
+    pub fn check<T>(&mut self, result: Result<T, MpdError>) -> Result<T, MpdError> {
+        if let Err(MpdError::Io(err)) = &result {
+            self.lost(err.to_string());
+        }
+        result
+    }
+
+    pub fn lost(&mut self, error: String) {
+        if self.client.take().is_some() {
+            self.backoff.failed();
+        }
+        self.error = Some(error);
+    }
+
+    /// Lets the next use connect right away, e.g. once the idle thread has
+    /// reached the server again.
+    pub fn retry_now(&mut self) {
+        self.backoff.reset();
+    }
+
+    /// Drops the connection so the next use connects with new settings.
+    pub fn reset(&mut self) {
+        *self = Connection::default();
+    }
+
+    /// A short description for the bottom bar.
+    pub fn describe(&self) -> String {
+        match (&self.client, &self.error) {
+            (Some(client), _) => format!("MPD {}", client.version()),
+            (None, Some(error)) => format!("MPD disconnected: {}", error),
+            (None, None) => String::from("MPD not connected"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::default();
+        assert!(backoff.ready());
+        let mut delays = Vec::new();
+        for _ in 0..8 {
+            backoff.failed();
+            delays.push(backoff.delay.as_secs());
+        }
+        assert_eq!(delays, [2, 4, 8, 16, 30, 30, 30, 30]);
+        assert!(!backoff.ready());
+        assert!(backoff.remaining() <= MAX_DELAY);
+    }
+
+    #[test]
+    fn reset_allows_an_attempt_now() {
+        let mut backoff = Backoff::default();
+        backoff.failed();
+        backoff.failed();
+        assert!(!backoff.ready());
+        backoff.reset();
+        assert!(backoff.ready());
+        assert_eq!(backoff.delay, MIN_DELAY);
+        backoff.failed();
+        assert_eq!(backoff.remaining().as_secs_f64().ceil() as u64, MIN_DELAY.as_secs());
+    }
+}
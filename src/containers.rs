@@ -1,14 +1,23 @@
-use std::io::Stdout;
-use termion::input::MouseTerminal;
-use termion::raw::RawTerminal;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use termion::event::Key;
 use tui::backend::{TermionBackend};
 use tui::Frame;
-use tui::style::{Style, Color};
+use tui::style::{Color, Modifier, Style};
 use tui::layout::{Alignment, Rect};
-use tui::text::{Span, Text};
+use tui::text::Text;
 use tui::widgets::{Block, Borders, Paragraph, Wrap};
+use crate::config::Config;
+use crate::frame::FrameOptions;
+use crate::layout::{self, LayoutError};
+use crate::mpd::{MpdClient, MpdError};
+use crate::registry::Registry;
 
-pub type F = TermionBackend<MouseTerminal<RawTerminal<Stdout>>>;
+/// The terminal drawn to, which reports the mouse unless turned off.
+pub type F = TermionBackend<Box<dyn Write>>;
 
 pub trait Container {
     fn draw(&self, f: &mut Frame<F>, area: Rect);
@@ -19,6 +28,11 @@ pub trait Container {
 
     fn unset_override_style(&mut self);
 
+    /// Sets the focus state of this container and everything below it.
+    fn set_focus(&mut self, focus: Focus);
+
+    fn set_focus_style(&mut self, style: FocusStyle);
+
     fn set_child(&mut self, index: usize, child: Box<dyn Container>);
 
     fn get_child(&self, index: u8) -> Option<&Box<dyn Container>>;
@@ -32,25 +46,346 @@ pub trait Container {
     fn get_widget_mut(&mut self) -> Option<&mut Box<dyn Widget>>;
 
     fn has_children(&self) -> bool;
+
+    /// The areas `draw` gives each child, in child order. Hidden children get
+    /// an empty rect.
+    fn child_areas(&self, area: Rect) -> Vec<Rect>;
+
+    /// Whether this container is drawn full-screen in place of the whole tree.
+    fn is_zoomed(&self) -> bool;
+
+    fn set_zoomed(&mut self, zoomed: bool);
+
+    /// Whether the child at `index` is collapsed to its title line.
+    fn is_collapsed(&self, index: u8) -> bool;
+
+    fn set_collapsed(&mut self, index: u8, collapsed: bool);
+
+    /// Serializes this container and its children for the layout file.
+    fn to_value(&self) -> Value;
 }
 
 pub trait Widget {
     fn draw(&self, f: &mut Frame<F>, area: Rect);
 
-    fn get_style(&self) -> WStyle;
+    /// The title, styles, frame and focus state every widget has.
+    fn pane(&self) -> &Pane;
 
-    fn set_override_style(&mut self, style: WStyleOpt);
+    fn pane_mut(&mut self) -> &mut Pane;
 
-    fn unset_override_style(&mut self);
+    fn to_value(&self) -> Value;
 
-    fn set_style(&mut self, style: WStyleOpt);
+    fn get_style(&self) -> WStyle {
+        self.pane().get_style()
+    }
+
+    fn set_override_style(&mut self, style: WStyleOpt) {
+        self.pane_mut().override_style = Some(style);
+    }
+
+    fn unset_override_style(&mut self) {
+        self.pane_mut().override_style = None;
+    }
+
+    fn set_style(&mut self, style: WStyleOpt) {
+        self.pane_mut().style.set(style);
+    }
+
+    fn set_focus(&mut self, focus: Focus) {
+        self.pane_mut().focus = focus;
+    }
+
+    fn set_focus_style(&mut self, style: FocusStyle) {
+        self.pane_mut().focus_style = style;
+    }
+
+    fn get_title(&self) -> String {
+        self.pane().title.clone()
+    }
+
+    fn set_title(&mut self, title: String) {
+        self.pane_mut().title = title;
+    }
+
+    /// The editable body text, for widgets that have one.
+    fn get_text(&self) -> Option<String> {
+        None
+    }
+
+    fn set_text(&mut self, text: String) {
+    }
+
+    fn get_frame_mut(&mut self) -> Option<&mut FrameOptions> {
+        Some(&mut self.pane_mut().frame)
+    }
+
+    /// Called when MPD reports changes to `subsystems`, with a connection to
+    /// fetch what changed.
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+    }
+
+    /// Called when the connection to MPD is lost. Widgets showing server
+    /// state should drop it rather than keep showing stale data.
+    fn on_disconnect(&mut self) {
+    }
+
+    /// Called in Normal mode with keys the app has no use for, while this
+    /// widget is focused. `mpd` is `None` if MPD cannot be reached. Returns
+    /// whether the key was used.
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        Ok(false)
+    }
+
+    /// A message for the bottom bar about what the last key did, if there is
+    /// one. It is shown once.
+    fn take_status(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Whether the widget is taking text input, so keys should reach it
+    /// before the playback keys.
+    fn is_editing(&self) -> bool {
+        false
+    }
+
+    /// Client-to-client channels the widget wants messages from. The idle
+    /// connection subscribes to the channels of all widgets.
+    fn channels(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Called with a message that arrived on a subscribed channel. Every
+    /// widget gets every message, so check `channel`.
+    fn on_message(&mut self, channel: &str, message: &str) {
+    }
+
+    /// The URI of the song under the cursor, for widgets listing songs.
+    fn selected_song(&self) -> Option<String> {
+        None
+    }
+
+    /// Called when the song selected in a song list changes, with `None`
+    /// when no list has one, and a connection to fetch it.
+    fn on_song_selected(&mut self, mpd: &mut MpdClient, config: &Config, file: Option<&str>) {
+    }
 }
 
+/// State shared by all widgets: what the frame looks like and how the widget
+/// is styled. Widgets keep one and hand it out through `Widget::pane`.
+pub struct Pane {
+    pub title: String,
+    pub style: WStyle,
+    pub frame: FrameOptions,
+    /// The MPD partition the widget controls, if not the configured one.
+    pub partition: Option<String>,
+    focus: Focus,
+    focus_style: FocusStyle,
+    override_style: Option<WStyleOpt>,
+}
 
+impl Pane {
+    pub fn new(title: String) -> Self {
+        Pane {
+            title,
+            style: WStyle::default(),
+            frame: FrameOptions::default(),
+            partition: None,
+            focus: Focus::None,
+            focus_style: FocusStyle::default(),
+            override_style: None,
+        }
+    }
+
+    /// The widget's style with the focus and override layers applied.
+    pub fn get_style(&self) -> WStyle {
+        let mut style = self.style.clone();
+        if let Some(focus_style) = self.focus_style.get(self.focus) {
+            style.set(focus_style.clone());
+        }
+        if let Some(override_style) = &self.override_style {
+            style.set(override_style.clone());
+        }
+        style
+    }
+
+    pub fn focus(&self) -> Focus {
+        self.focus
+    }
+
+    /// Draws the frame and returns the content area with the style to draw it in.
+    pub fn draw(&self, f: &mut Frame<F>, area: Rect) -> (Rect, WStyle) {
+        let style = self.get_style();
+        let title = match &self.partition {
+            Some(partition) if self.title.is_empty() => format!("[{}]", partition),
+            Some(partition) => format!("{} [{}]", self.title, partition),
+            None => self.title.clone(),
+        };
+        let inner = self.frame.draw(f, area, &title, style.title_style, style.border_style);
+        (inner, style)
+    }
+
+    /// The layout value for a widget of type `kind`. Widgets add their own
+    /// fields to it.
+    pub fn to_value(&self, kind: &str) -> Value {
+        json!({
+            "type": kind,
+            "title": self.title,
+            "style": self.style,
+            "frame": self.frame,
+            "partition": self.partition,
+        })
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        #[derive(Deserialize)]
+        struct Saved {
+            #[serde(default)]
+            title: String,
+            #[serde(default)]
+            style: WStyle,
+            #[serde(default)]
+            frame: FrameOptions,
+            #[serde(default)]
+            partition: Option<String>,
+        }
+        let saved = Saved::deserialize(value)?;
+        let mut pane = Pane::new(saved.title);
+        pane.style = saved.style;
+        pane.frame = saved.frame;
+        pane.partition = saved.partition;
+        Ok(pane)
+    }
+}
+
+/// Width of a collapsed child in a horizontal split.
+const COLLAPSED_WIDTH: u16 = 16;
+
+/// Finds the zoomed container at or below `container`, if any.
+pub fn find_zoomed(container: &Box<dyn Container>) -> Option<&Box<dyn Container>> {
+    if container.is_zoomed() {
+        return Some(container);
+    }
+    (0..=u8::MAX).map_while(|index| container.get_child(index)).find_map(find_zoomed)
+}
+
+/// Calls `f` on every widget at or below `container`.
+pub fn visit_widgets_mut(container: &mut Box<dyn Container>, f: &mut dyn FnMut(&mut Box<dyn Widget>)) {
+    if let Some(widget) = container.get_widget_mut() {
+        f(widget);
+    }
+    let mut index = 0;
+    while let Some(child) = container.get_child_mut(index) {
+        visit_widgets_mut(child, f);
+        index += 1;
+    }
+}
+
+/// The child indices leading from `container` to the first widget `f`
+/// accepts, if any.
+pub fn find_widget(container: &Box<dyn Container>, f: &dyn Fn(&Box<dyn Widget>) -> bool) -> Option<Vec<u8>> {
+    if container.get_widget().is_some_and(f) {
+        return Some(Vec::new());
+    }
+    (0..=u8::MAX).map_while(|index| container.get_child(index).map(|child| (index, child))).find_map(|(index, child)| {
+        let mut path = find_widget(child, f)?;
+        path.insert(0, index);
+        Some(path)
+    })
+}
+
+/// The part of `rect` inside `area`, which is empty if they do not meet.
+/// Collapsed children keep their title line even where there is no room for
+/// it, so it is cut here rather than drawn outside the buffer.
+fn within(rect: Rect, area: Rect) -> Rect {
+    if rect.intersects(area) {
+        rect.intersection(area)
+    } else {
+        Rect::new(area.x, area.y, 0, 0)
+    }
+}
+
+/// Unzooms `container` and everything below it.
+pub fn clear_zoom(container: &mut Box<dyn Container>) {
+    container.set_zoomed(false);
+    let mut index = 0;
+    while let Some(child) = container.get_child_mut(index) {
+        clear_zoom(child);
+        index += 1;
+    }
+}
+
+/// Where a widget sits relative to the current focus. Each state other than
+/// `None` maps to a layer in `FocusStyle`, applied between the widget's own
+/// style and its override style.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Focus {
+    #[default]
+    None,
+    /// The leaf that currently has focus.
+    Focused,
+    /// Inside the container that holds the focus or Select mode candidate.
+    Parent,
+    /// The child that Select mode will replace or delete.
+    Candidate,
+}
+
+/// The styles laid over panes by where they are relative to the focus. The
+/// config's `focus_style` and a theme file hold one as JSON, with any of the
+/// styles left out keeping their defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FocusStyle {
+    focused: WStyleOpt,
+    parent: WStyleOpt,
+    candidate: WStyleOpt,
+}
+
+impl Default for FocusStyle {
+    fn default() -> Self {
+        FocusStyle {
+            focused: WStyleOpt::default()
+                .set_border_style(Style::default().fg(Color::LightYellow).add_modifier(Modifier::BOLD))
+                .set_title_style(Style::default().fg(Color::LightYellow).add_modifier(Modifier::BOLD)),
+            parent: WStyleOpt::default()
+                .set_border_style(Style::default().fg(Color::Blue)),
+            candidate: WStyleOpt::default()
+                .set_border_style(Style::default().fg(Color::Yellow)),
+        }
+    }
+}
+
+impl FocusStyle {
+    pub fn new(focused: WStyleOpt, parent: WStyleOpt, candidate: WStyleOpt) -> Self {
+        FocusStyle {
+            focused,
+            parent,
+            candidate,
+        }
+    }
+
+    /// Reads a theme file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        serde_json::from_str(&text).map_err(|err| format!("invalid theme: {}", err))
+    }
+
+    pub fn get(&self, focus: Focus) -> Option<&WStyleOpt> {
+        match focus {
+            Focus::None => None,
+            Focus::Focused => Some(&self.focused),
+            Focus::Parent => Some(&self.parent),
+            Focus::Candidate => Some(&self.candidate),
+        }
+    }
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WStyle {
-    title_style: Style,
-    text_style: Style,
-    border_style: Style,
+    pub title_style: Style,
+    pub text_style: Style,
+    pub border_style: Style,
 }
 
 impl Default for WStyle {
@@ -63,16 +398,6 @@ impl Default for WStyle {
     }
 }
 
-impl Clone for WStyle {
-    fn clone(&self) -> Self {
-        WStyle {
-            title_style: self.title_style.clone(),
-            text_style: self.text_style.clone(),
-            border_style: self.border_style.clone(),
-        }
-    }
-}
-
 impl From<WStyleOpt> for WStyle {
     fn from(style: WStyleOpt) -> Self {
         let default = WStyle::default();
@@ -107,33 +432,25 @@ impl WStyle {
     }
 }
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WStyleOpt {
     title_style: Option<Style>,
     text_style: Option<Style>,
     border_style: Option<Style>,
 }
 
-impl Default for WStyleOpt {
-    fn default() -> Self {
-        WStyleOpt {
-            title_style: None,
-            text_style: None,
-            border_style: None,
-        }
+impl WStyleOpt {
+    pub fn set_title_style(mut self, style: Style) -> Self {
+        self.title_style = Some(style);
+        self
     }
-}
 
-impl Clone for WStyleOpt {
-    fn clone(&self) -> Self {
-        WStyleOpt {
-            title_style: self.title_style,
-            text_style: self.text_style,
-            border_style: self.border_style,
-        }
+    pub fn set_text_style(mut self, style: Style) -> Self {
+        self.text_style = Some(style);
+        self
     }
-}
 
-impl WStyleOpt {
     pub fn set_border_style(mut self, style: Style) -> Self {
         self.border_style = Some(style);
         self
@@ -143,6 +460,7 @@ impl WStyleOpt {
 
 pub struct BasicContainer {
     child: Box<dyn Widget>,
+    zoomed: bool,
 }
 
 impl Container for BasicContainer {
@@ -162,6 +480,14 @@ impl Container for BasicContainer {
         self.child.unset_override_style();
     }
 
+    fn set_focus(&mut self, focus: Focus) {
+        self.child.set_focus(focus);
+    }
+
+    fn set_focus_style(&mut self, style: FocusStyle) {
+        self.child.set_focus_style(style);
+    }
+
     fn set_child(&mut self, index: usize, child: Box<dyn Container>) {
     }
 
@@ -188,12 +514,40 @@ impl Container for BasicContainer {
     fn has_children(&self) -> bool {
         false
     }
+
+    fn child_areas(&self, area: Rect) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    fn is_zoomed(&self) -> bool {
+        self.zoomed
+    }
+
+    fn set_zoomed(&mut self, zoomed: bool) {
+        self.zoomed = zoomed;
+    }
+
+    fn is_collapsed(&self, index: u8) -> bool {
+        false
+    }
+
+    fn set_collapsed(&mut self, index: u8, collapsed: bool) {
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "type": "basic",
+            "zoomed": self.zoomed,
+            "widget": self.child.to_value(),
+        })
+    }
 }
 
 impl Default for BasicContainer {
     fn default() -> Self {
         BasicContainer {
             child: Box::new(BasicWidget::default()),
+            zoomed: false,
         }
     }
 }
@@ -202,8 +556,16 @@ impl BasicContainer {
     pub fn new(child: Box<dyn Widget>) -> Self {
         BasicContainer {
             child,
+            zoomed: false,
         }
     }
+
+    pub fn from_value(value: &Value, registry: &Registry) -> Result<Self, LayoutError> {
+        let widget = value.get("widget").ok_or(LayoutError::Missing("widget"))?;
+        let mut container = BasicContainer::new(layout::widget_from_value(widget, registry)?);
+        container.zoomed = layout::zoomed_from_value(value);
+        Ok(container)
+    }
 }
 
 
@@ -216,7 +578,10 @@ impl Container for RootContainer {
         if area.width < 2 || area.height < 2 {
             return;
         }
-        self.child.draw(f, area);
+        match find_zoomed(&self.child) {
+            Some(zoomed) => zoomed.draw(f, area),
+            None => self.child.draw(f, area),
+        }
     }
 
     fn set_style(&mut self, style: WStyleOpt) {
@@ -231,10 +596,17 @@ impl Container for RootContainer {
         self.child.unset_override_style();
     }
 
+    fn set_focus(&mut self, focus: Focus) {
+        self.child.set_focus(focus);
+    }
+
+    fn set_focus_style(&mut self, style: FocusStyle) {
+        self.child.set_focus_style(style);
+    }
+
     fn set_child(&mut self, index: usize, child: Box<dyn Container>) {
-        match index {
-            0 => self.child = child,
-            _ => (),
+        if index == 0 {
+            self.child = child;
         }
     }
 
@@ -266,6 +638,31 @@ impl Container for RootContainer {
     fn has_children(&self) -> bool {
         true
     }
+
+    fn child_areas(&self, area: Rect) -> Vec<Rect> {
+        vec![area]
+    }
+
+    fn is_zoomed(&self) -> bool {
+        false
+    }
+
+    fn set_zoomed(&mut self, zoomed: bool) {
+    }
+
+    fn is_collapsed(&self, index: u8) -> bool {
+        false
+    }
+
+    fn set_collapsed(&mut self, index: u8, collapsed: bool) {
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "type": "root",
+            "child": self.child.to_value(),
+        })
+    }
 }
 
 impl Default for RootContainer {
@@ -286,46 +683,29 @@ impl RootContainer {
     pub fn into_dyn_container(self) -> Box<dyn Container> {
         Box::new(self)
     }
+
+    pub fn from_value(value: &Value, registry: &Registry) -> Result<Self, LayoutError> {
+        let child = value.get("child").ok_or(LayoutError::Missing("child"))?;
+        Ok(RootContainer {
+            child: layout::container_from_value(child, registry)?,
+        })
+    }
 }
 
 
 pub struct HSplitContainer {
     children: Vec<Box<dyn Container>>,
     split: f32,
+    collapsed: [bool; 2],
+    zoomed: bool,
 }
 
 impl Container for HSplitContainer {
     fn draw(&self, f: &mut Frame<F>, area: Rect) {
-        let area = area;
-        let mut split = self.split;
-        if split < 0.0 {
-            split = 0.0;
-        } else if split > 1.0 {
-            split = 1.0;
-        }
-        let split = split * area.width as f32;
-        let split = split as u16;
-        let left = Rect {
-            x: area.x,
-            y: area.y,
-            width: split,
-            height: area.height,
-        };
-        let right = Rect {
-            x: area.x + split,
-            y: area.y,
-            width: area.width - split,
-            height: area.height,
-        };
-        if left.width < 2 || right.width < 2 {
-            if self.split > 0.5 {
-                self.children[0].draw(f, area);
-            } else {
-                self.children[1].draw(f, area);
+        for (child, rect) in self.children.iter().zip(self.child_areas(area)) {
+            if rect.width > 0 && rect.height > 0 {
+                child.draw(f, rect);
             }
-        } else {
-            self.children[0].draw(f, left);
-            self.children[1].draw(f, right);
         }
     }
 
@@ -344,6 +724,16 @@ impl Container for HSplitContainer {
         self.children[1].unset_override_style();
     }
 
+    fn set_focus(&mut self, focus: Focus) {
+        self.children[0].set_focus(focus);
+        self.children[1].set_focus(focus);
+    }
+
+    fn set_focus_style(&mut self, style: FocusStyle) {
+        self.children[0].set_focus_style(style.clone());
+        self.children[1].set_focus_style(style);
+    }
+
     fn set_child(&mut self, index: usize, child: Box<dyn Container>) {
         if index < self.children.len() {
             self.children[index] = child;
@@ -380,6 +770,58 @@ impl Container for HSplitContainer {
     fn has_children(&self) -> bool {
         true
     }
+
+    fn child_areas(&self, area: Rect) -> Vec<Rect> {
+        let collapsed_width = COLLAPSED_WIDTH.min(area.width / 2);
+        let collapsed = |x: u16| Rect::new(x, area.y, collapsed_width, 1);
+        let rest = |x: u16| Rect::new(x, area.y, area.width - collapsed_width, area.height);
+        let areas = match self.collapsed {
+            [true, false] => vec![collapsed(area.x), rest(area.x + collapsed_width)],
+            [false, true] => vec![rest(area.x), collapsed(area.right() - collapsed_width)],
+            [true, true] => vec![collapsed(area.x), collapsed(area.x + collapsed_width)],
+            [false, false] => {
+                let split = (self.split.clamp(0.0, 1.0) * area.width as f32) as u16;
+                let left = Rect::new(area.x, area.y, split, area.height);
+                let right = Rect::new(area.x + split, area.y, area.width - split, area.height);
+                if left.width >= 2 && right.width >= 2 {
+                    vec![left, right]
+                } else if self.split > 0.5 {
+                    vec![area, Rect::default()]
+                } else {
+                    vec![Rect::default(), area]
+                }
+            }
+        };
+        areas.into_iter().map(|rect| within(rect, area)).collect()
+    }
+
+    fn is_zoomed(&self) -> bool {
+        self.zoomed
+    }
+
+    fn set_zoomed(&mut self, zoomed: bool) {
+        self.zoomed = zoomed;
+    }
+
+    fn is_collapsed(&self, index: u8) -> bool {
+        self.collapsed.get(index as usize).copied().unwrap_or(false)
+    }
+
+    fn set_collapsed(&mut self, index: u8, collapsed: bool) {
+        if let Some(state) = self.collapsed.get_mut(index as usize) {
+            *state = collapsed;
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "type": "hsplit",
+            "split": self.split,
+            "collapsed": self.collapsed,
+            "zoomed": self.zoomed,
+            "children": [self.children[0].to_value(), self.children[1].to_value()],
+        })
+    }
 }
 
 impl Default for HSplitContainer {
@@ -387,6 +829,8 @@ impl Default for HSplitContainer {
         HSplitContainer {
             children: vec![Box::new(BasicContainer::default()), Box::new(BasicContainer::default())],
             split: 0.5,
+            collapsed: [false; 2],
+            zoomed: false,
         }
     }
 }
@@ -396,52 +840,38 @@ impl HSplitContainer {
         HSplitContainer {
             children: vec![left, right],
             split,
+            collapsed: [false; 2],
+            zoomed: false,
         }
     }
 
     fn set_split(&mut self, split: f32) {
         self.split = split;
     }
+
+    pub fn from_value(value: &Value, registry: &Registry) -> Result<Self, LayoutError> {
+        let (left, right, split) = layout::split_from_value(value, registry)?;
+        let mut container = HSplitContainer::new(left, right, split);
+        container.collapsed = layout::collapsed_from_value(value);
+        container.zoomed = layout::zoomed_from_value(value);
+        Ok(container)
+    }
 }
 
 
 pub struct VSplitContainer {
     children: Vec<Box<dyn Container>>,
     split: f32,
+    collapsed: [bool; 2],
+    zoomed: bool,
 }
 
 impl Container for VSplitContainer {
     fn draw(&self, f: &mut Frame<F>, area: Rect) {
-        let area = area;
-        let mut split = self.split;
-        if split < 0.0 {
-            split = 0.0;
-        } else if split > 1.0 {
-            split = 1.0;
-        }
-        let split = split * area.height as f32;
-        let split = split as u16;
-        let top = Rect {
-            x: area.x,
-            y: area.y,
-            width: area.width,
-            height: split,
-        };
-        let bottom = Rect {
-            x: area.x,
-            y: area.y + split,
-            width: area.width,
-            height: area.height - split,
-        };
-        if top.height < 2 || bottom.height < 2 {
-            if self.split > 0.5 {
-                self.children[0].draw(f, area);
-            } else {
-                self.children[1].draw(f, area);
+        for (child, rect) in self.children.iter().zip(self.child_areas(area)) {
+            if rect.width > 0 && rect.height > 0 {
+                child.draw(f, rect);
             }
-        } else {
-            self.children[0].draw(f, top);
-            self.children[1].draw(f, bottom);
         }
     }
 
@@ -460,6 +890,16 @@ impl Container for VSplitContainer {
         self.children[1].unset_override_style();
     }
 
+    fn set_focus(&mut self, focus: Focus) {
+        self.children[0].set_focus(focus);
+        self.children[1].set_focus(focus);
+    }
+
+    fn set_focus_style(&mut self, style: FocusStyle) {
+        self.children[0].set_focus_style(style.clone());
+        self.children[1].set_focus_style(style);
+    }
+
     fn set_child(&mut self, index: usize, child: Box<dyn Container>) {
         if index < self.children.len() {
             self.children[index] = child;
@@ -496,6 +936,57 @@ impl Container for VSplitContainer {
     fn has_children(&self) -> bool {
         true
     }
+
+    fn child_areas(&self, area: Rect) -> Vec<Rect> {
+        let collapsed = |y: u16| Rect::new(area.x, y, area.width, 1);
+        let rest = |y: u16| Rect::new(area.x, y, area.width, area.height.saturating_sub(1));
+        let areas = match self.collapsed {
+            [true, false] => vec![collapsed(area.y), rest(area.y + 1)],
+            [false, true] => vec![rest(area.y), collapsed(area.bottom().saturating_sub(1))],
+            [true, true] => vec![collapsed(area.y), collapsed(area.y + 1)],
+            [false, false] => {
+                let split = (self.split.clamp(0.0, 1.0) * area.height as f32) as u16;
+                let top = Rect::new(area.x, area.y, area.width, split);
+                let bottom = Rect::new(area.x, area.y + split, area.width, area.height - split);
+                if top.height >= 2 && bottom.height >= 2 {
+                    vec![top, bottom]
+                } else if self.split > 0.5 {
+                    vec![area, Rect::default()]
+                } else {
+                    vec![Rect::default(), area]
+                }
+            }
+        };
+        areas.into_iter().map(|rect| within(rect, area)).collect()
+    }
+
+    fn is_zoomed(&self) -> bool {
+        self.zoomed
+    }
+
+    fn set_zoomed(&mut self, zoomed: bool) {
+        self.zoomed = zoomed;
+    }
+
+    fn is_collapsed(&self, index: u8) -> bool {
+        self.collapsed.get(index as usize).copied().unwrap_or(false)
+    }
+
+    fn set_collapsed(&mut self, index: u8, collapsed: bool) {
+        if let Some(state) = self.collapsed.get_mut(index as usize) {
+            *state = collapsed;
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "type": "vsplit",
+            "split": self.split,
+            "collapsed": self.collapsed,
+            "zoomed": self.zoomed,
+            "children": [self.children[0].to_value(), self.children[1].to_value()],
+        })
+    }
 }
 
 impl Default for VSplitContainer {
@@ -503,6 +994,8 @@ impl Default for VSplitContainer {
         VSplitContainer {
             children: vec![Box::new(BasicContainer::default()), Box::new(BasicContainer::default())],
             split: 0.5,
+            collapsed: [false; 2],
+            zoomed: false,
         }
     }
 }
@@ -512,73 +1005,126 @@ impl VSplitContainer {
         VSplitContainer {
             children: vec![top, bottom],
             split,
+            collapsed: [false; 2],
+            zoomed: false,
         }
     }
+
+    pub fn from_value(value: &Value, registry: &Registry) -> Result<Self, LayoutError> {
+        let (top, bottom, split) = layout::split_from_value(value, registry)?;
+        let mut container = VSplitContainer::new(top, bottom, split);
+        container.collapsed = layout::collapsed_from_value(value);
+        container.zoomed = layout::zoomed_from_value(value);
+        Ok(container)
+    }
 }
 
 
 pub struct BasicWidget {
-    title: String,
+    pane: Pane,
     text: String,
-    style: WStyle,
-    override_style: Option<WStyleOpt>,
 }
 
 impl Widget for BasicWidget {
     fn draw(&self, f: &mut Frame<F>, area: Rect) {
-        let local_style = self.get_style();
-        let rect = area;
-        let block = Block::default()
-            .borders(Borders::ALL)
-            .border_style(local_style.border_style)
-            .title(Span::styled(self.title.clone(), local_style.title_style));
-        f.render_widget(block, rect);
+        let (inner, local_style) = self.pane.draw(f, area);
         let text = Text::styled(self.text.clone(), local_style.text_style);
         let text = Paragraph::new(text)
             .block(Block::default().borders(Borders::NONE))
             .wrap(Wrap { trim: true })
             .alignment(Alignment::Left);
-        f.render_widget(text, Rect::new(rect.x + 1, rect.y + 1, rect.width - 2, rect.height - 2));
+        f.render_widget(text, inner);
     }
 
-    fn get_style(&self) -> WStyle {
-        match &self.override_style {
-            Some(style) => self.style.clone().set(style.clone()).to_owned(),
-            None => self.style.clone(),
-        }
+    fn pane(&self) -> &Pane {
+        &self.pane
     }
 
-    fn set_override_style(&mut self, style: WStyleOpt) {
-        self.override_style = Some(style);
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
     }
 
-    fn unset_override_style(&mut self) {
-        self.override_style = None;
+    fn to_value(&self) -> Value {
+        let mut value = self.pane.to_value("basic");
+        value["text"] = json!(self.text);
+        value
     }
 
-    fn set_style(&mut self, style: WStyleOpt) {
-        self.style.set(style);
+    fn get_text(&self) -> Option<String> {
+        Some(self.text.clone())
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.text = text;
     }
 }
 
 impl Default for BasicWidget {
     fn default() -> Self {
-        BasicWidget {
-            title: String::from(""),
-            text: String::from(""),
-            style: WStyle::default(),
-            override_style: None
-        }
+        BasicWidget::new(String::new(), String::new())
     }
 }
 
 impl BasicWidget {
     pub fn new(title: String, text: String) -> Self {
         BasicWidget {
-            title,
+            pane: Pane::new(title),
             text,
-            style: WStyle::default(),
-            override_style: None
         }
     }
-}
\ No newline at end of file
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        let text = value.get("text").and_then(Value::as_str).unwrap_or("").to_string();
+        Ok(BasicWidget {
+            pane: Pane::from_value(value)?,
+            text,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use tui::{Terminal, TerminalOptions, Viewport};
+    use super::*;
+
+    fn collapsed(vertical: bool) -> Box<dyn Container> {
+        let titled = |title: &str| Box::new(BasicContainer::new(Box::new(BasicWidget::new(title.to_string(), String::new()))));
+        let (first, second) = (titled("first"), titled("second"));
+        let mut split: Box<dyn Container> = if vertical {
+            Box::new(VSplitContainer::new(first, second, 0.5))
+        } else {
+            Box::new(HSplitContainer::new(first, second, 0.5))
+        };
+        split.set_collapsed(0, true);
+        split.set_collapsed(1, true);
+        split
+    }
+
+    #[test]
+    fn child_areas_stay_inside_small_areas() {
+        for vertical in [true, false] {
+            let split = collapsed(vertical);
+            for area in [Rect::new(3, 9, 20, 1), Rect::new(3, 9, 20, 0), Rect::new(3, 9, 1, 1), Rect::new(3, 9, 0, 0)] {
+                for rect in split.child_areas(area) {
+                    assert!(rect.x >= area.x && rect.right() <= area.right(), "{:?} outside {:?}", rect, area);
+                    assert!(rect.y >= area.y && rect.bottom() <= area.bottom(), "{:?} outside {:?}", rect, area);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn both_collapsed_split_draws_on_the_last_row() {
+        let area = Rect::new(0, 0, 20, 5);
+        let backend: Box<dyn Write> = Box::new(Vec::new());
+        let options = TerminalOptions { viewport: Viewport::fixed(area) };
+        let mut terminal = Terminal::with_options(TermionBackend::new(backend), options).unwrap();
+        let split = collapsed(true);
+        let frame = terminal.draw(|f| split.draw(f, Rect::new(0, 4, 20, 1))).unwrap();
+        let row = |y: u16| (0..area.width).map(|x| frame.buffer.get(x, y).symbol.as_str()).collect::<String>();
+        assert!(row(4).contains("first"), "row 4 is {:?}", row(4));
+        assert!(!row(4).contains("second"));
+        assert!((0..4).all(|y| row(y).trim().is_empty()));
+    }
+}
@@ -0,0 +1,209 @@
+use std::{env, fs, io, thread};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::ThingEvent;
+use crate::actions::Action;
+use crate::config;
+
+/// `mpd-thing-rs.sock` in `$XDG_RUNTIME_DIR`, or in the config directory
+/// without one.
+pub fn default_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("mpd-thing-rs.sock"),
+        _ => config::config_dir().join("mpd-thing-rs.sock"),
+    }
+}
+
+/// What a script can ask the running app to do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Focuses the pane with this title, or the first of this widget type.
+    Focus(String),
+    /// Zooms or unzooms a pane, or the focused one.
+    Zoom(Option<String>),
+    /// Loads a layout file, or reloads the current one.
+    LoadLayout(Option<String>),
+    Action(Action),
+    /// Switches to the server profile with this name.
+    Profile(String),
+    /// Replies with the mode, connection and panes.
+    State,
+}
+
+/// The JSON form of a command, as `{"command": "focus", "argument": "Queue"}`.
+#[derive(Deserialize)]
+struct JsonCommand {
+    command: String,
+    argument: Option<String>,
+}
+
+impl Command {
+    /// Parses a line, either `command [argument]` or a JSON object.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, argument) = if line.starts_with('{') {
+            let command: JsonCommand = serde_json::from_str(line).map_err(|err| format!("invalid command: {}", err))?;
+            (command.command, command.argument)
+        } else {
+            match line.split_once(char::is_whitespace) {
+                Some((name, argument)) => (name.to_string(), Some(argument.trim().to_string())),
+                None => (line.to_string(), None),
+            }
+        };
+        let argument = argument.filter(|argument| !argument.is_empty());
+        let required = |argument: Option<String>| argument.ok_or_else(|| format!("{} needs an argument", name));
+        Ok(match name.as_str() {
+            "focus" => Command::Focus(required(argument)?),
+            "zoom" => Command::Zoom(argument),
+            "load-layout" => Command::LoadLayout(argument),
+            "action" => {
+                let id = required(argument)?;
+                Command::Action(Action::from_id(&id).ok_or_else(|| format!("unknown action `{}`", id))?)
+            }
+            "profile" => Command::Profile(required(argument)?),
+            "state" => Command::State,
+            "" => return Err(String::from("empty command")),
+            _ => return Err(format!("unknown command `{}`", name)),
+        })
+    }
+}
+
+/// A command from a client, with the way back to it. Every request gets
+/// exactly one reply; dropping it unanswered tells the client it failed.
+pub struct Request {
+    pub command: Command,
+    reply: mpsc::Sender<Value>,
+}
+
+impl Request {
+    /// A request nobody waits on, as scripts make.
+    pub fn detached(command: Command) -> Self {
+        let (reply, _) = mpsc::channel();
+        Request { command, reply }
+    }
+
+    /// Answers with a message, or the error that kept the command from
+    /// running.
+    pub fn reply(self, result: Result<String, String>) {
+        let _ = self.reply.send(match result {
+            Ok(message) => json!({ "ok": true, "message": message }),
+            Err(error) => error_reply(&error),
+        });
+    }
+
+    /// Answers a `State` request.
+    pub fn reply_state(self, state: Value) {
+        let _ = self.reply.send(json!({ "ok": true, "state": state }));
+    }
+}
+
+fn error_reply(error: &str) -> Value {
+    json!({ "ok": false, "error": error })
+}
+
+/// The socket scripts control the app through. Commands arrive as
+/// `ThingEvent::Control`, one per line, and every line is answered with a
+/// line of JSON. The socket file is removed when this is dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listens on `path`, replacing a socket left behind by an app that did
+    /// not exit cleanly. Fails if another instance is listening there.
+    pub fn listen(path: &Path, events: mpsc::Sender<ThingEvent>) -> io::Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "another instance is listening"));
+            }
+            fs::remove_file(path)?;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let events = events.clone();
+                thread::spawn(move || serve(stream, events));
+            }
+        });
+        Ok(ControlSocket {
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answers a client's commands until it hangs up or the app exits.
+fn serve(stream: UnixStream, events: mpsc::Sender<ThingEvent>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match Command::parse(&line) {
+            Ok(command) => {
+                let (reply_tx, reply_rx) = mpsc::channel();
+                if events.send(ThingEvent::Control(Request { command, reply: reply_tx })).is_err() {
+                    return Ok(());
+                }
+                reply_rx.recv().unwrap_or_else(|_| error_reply("the app did not answer"))
+            }
+            Err(error) => error_reply(&error),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_take_the_rest_as_argument() {
+        assert_eq!(Command::parse("focus  Song Info "), Ok(Command::Focus(String::from("Song Info"))));
+        assert_eq!(Command::parse("zoom"), Ok(Command::Zoom(None)));
+        assert_eq!(Command::parse("load-layout ~/a.json"), Ok(Command::LoadLayout(Some(String::from("~/a.json")))));
+        assert_eq!(Command::parse("action next"), Ok(Command::Action(Action::Next)));
+        assert_eq!(Command::parse("state\n"), Ok(Command::State));
+    }
+
+    #[test]
+    fn json_matches_the_line_form() {
+        assert_eq!(Command::parse(r#"{"command": "profile", "argument": "home"}"#), Ok(Command::Profile(String::from("home"))));
+        assert_eq!(Command::parse(r#"{"command": "zoom", "argument": ""}"#), Ok(Command::Zoom(None)));
+        assert_eq!(Command::parse(r#"{"command": "action", "argument": "toggle-pause"}"#), Ok(Command::Action(Action::TogglePause)));
+        assert!(Command::parse(r#"{"argument": "Queue"}"#).unwrap_err().starts_with("invalid command"));
+        assert!(Command::parse("{not json").is_err());
+    }
+
+    #[test]
+    fn missing_arguments_are_errors() {
+        assert_eq!(Command::parse("focus"), Err(String::from("focus needs an argument")));
+        assert_eq!(Command::parse("profile   "), Err(String::from("profile needs an argument")));
+        assert_eq!(Command::parse(r#"{"command": "action"}"#), Err(String::from("action needs an argument")));
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert_eq!(Command::parse("dance"), Err(String::from("unknown command `dance`")));
+        assert_eq!(Command::parse("action dance"), Err(String::from("unknown action `dance`")));
+        assert_eq!(Command::parse("  "), Err(String::from("empty command")));
+    }
+}
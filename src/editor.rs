@@ -0,0 +1,358 @@
+use std::cell::Cell;
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::Rect;
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, Clear, Paragraph};
+use unicode_width::UnicodeWidthChar;
+use crate::containers::F;
+
+/// A small text editor with word-wrap aware cursor movement. Lines are stored
+/// unwrapped; wrapping only affects drawing and moving up or down.
+pub struct TextEditor {
+    lines: Vec<String>,
+    row: usize,
+    /// Cursor column in chars, not bytes.
+    col: usize,
+    multiline: bool,
+    /// Width the editor was last drawn at, used to move between wrapped rows.
+    wrap_width: Cell<usize>,
+}
+
+impl TextEditor {
+    pub fn new(text: &str, multiline: bool) -> Self {
+        let mut lines = text.split('\n').map(String::from).collect::<Vec<String>>();
+        if !multiline {
+            lines = vec![lines.join(" ")];
+        }
+        TextEditor {
+            lines,
+            row: 0,
+            col: 0,
+            multiline,
+            wrap_width: Cell::new(usize::MAX),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// Applies an editing key. Returns false for keys the editor ignores.
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char('\n') if self.multiline => self.newline(),
+            Key::Char('\n') | Key::Char('\t') => return false,
+            Key::Char(c) => self.insert(c),
+            Key::Backspace => self.backspace(),
+            Key::Delete => self.delete(),
+            Key::Left => self.left(),
+            Key::Right => self.right(),
+            Key::Up => self.vertical(false),
+            Key::Down => self.vertical(true),
+            Key::Home => self.col = self.visual_row_start(),
+            Key::End => self.col = self.visual_row_end(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn line_len(&self, row: usize) -> usize {
+        self.lines[row].chars().count()
+    }
+
+    fn byte_index(&self) -> usize {
+        let line = &self.lines[self.row];
+        line.char_indices().nth(self.col).map(|(index, _)| index).unwrap_or(line.len())
+    }
+
+    fn insert(&mut self, c: char) {
+        let index = self.byte_index();
+        self.lines[self.row].insert(index, c);
+        self.col += 1;
+    }
+
+    fn newline(&mut self) {
+        let index = self.byte_index();
+        let rest = self.lines[self.row].split_off(index);
+        self.row += 1;
+        self.lines.insert(self.row, rest);
+        self.col = 0;
+    }
+
+    fn backspace(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+            let index = self.byte_index();
+            self.lines[self.row].remove(index);
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.col < self.line_len(self.row) {
+            let index = self.byte_index();
+            self.lines[self.row].remove(index);
+        } else if self.row + 1 < self.lines.len() {
+            let line = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    fn left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+        }
+    }
+
+    fn right(&mut self) {
+        if self.col < self.line_len(self.row) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    /// Moves one wrapped row up or down, keeping the column within the row.
+    fn vertical(&mut self, down: bool) {
+        let width = self.wrap_width.get();
+        let rows = wrap(&self.lines[self.row], width);
+        let current = row_of(&rows, self.col);
+        let offset = self.col - rows[current].0;
+        if down && current + 1 < rows.len() {
+            self.col = column_in_row(&rows, current + 1, offset);
+        } else if !down && current > 0 {
+            self.col = column_in_row(&rows, current - 1, offset);
+        } else if down && self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = column_in_row(&wrap(&self.lines[self.row], width), 0, offset);
+        } else if !down && self.row > 0 {
+            self.row -= 1;
+            let rows = wrap(&self.lines[self.row], width);
+            self.col = column_in_row(&rows, rows.len() - 1, offset);
+        }
+    }
+
+    fn visual_row_start(&self) -> usize {
+        let rows = wrap(&self.lines[self.row], self.wrap_width.get());
+        rows[row_of(&rows, self.col)].0
+    }
+
+    fn visual_row_end(&self) -> usize {
+        let rows = wrap(&self.lines[self.row], self.wrap_width.get());
+        column_in_row(&rows, row_of(&rows, self.col), usize::MAX)
+    }
+
+    /// Draws the wrapped text into `area`, scrolled so the cursor is visible,
+    /// and places the terminal cursor if `active`.
+    pub fn draw(&self, f: &mut Frame<F>, area: Rect, style: Style, active: bool) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        let width = area.width as usize;
+        self.wrap_width.set(width);
+        let mut spans = Vec::new();
+        let mut cursor = (0, 0);
+        for (row, line) in self.lines.iter().enumerate() {
+            let chars = line.chars().collect::<Vec<char>>();
+            let rows = wrap(line, width);
+            if row == self.row {
+                let current = row_of(&rows, self.col);
+                cursor = (spans.len() + current, columns(&chars[rows[current].0..self.col]));
+            }
+            for (start, end) in rows {
+                spans.push(Spans::from(Span::styled(chars[start..end].iter().collect::<String>(), style)));
+            }
+        }
+        let scroll = (cursor.0 + 1).saturating_sub(area.height as usize);
+        f.render_widget(Paragraph::new(spans).scroll((scroll as u16, 0)), area);
+        if active {
+            let x = area.x + (cursor.1 as u16).min(area.width - 1);
+            let y = area.y + (cursor.0 - scroll) as u16;
+            f.set_cursor(x, y);
+        }
+    }
+
+    /// Draws `label` followed by the text on the one line `area`, as a
+    /// prompt at the bottom of a widget.
+    pub fn draw_prompt(&self, f: &mut Frame<F>, area: Rect, label: &str, label_style: Style, style: Style) {
+        let width = (label.chars().count() as u16).min(area.width);
+        f.render_widget(Paragraph::new(Span::styled(label.to_string(), label_style)), Rect { width, ..area });
+        let rest = Rect::new(area.x + width, area.y, area.width - width, 1);
+        self.draw(f, rest, style, true);
+    }
+}
+
+/// Edits a widget's title and, if it has one, its body text. Tab switches
+/// between the two.
+pub struct PaneEditor {
+    title: TextEditor,
+    body: Option<TextEditor>,
+    editing_body: bool,
+}
+
+impl PaneEditor {
+    pub fn new(title: &str, body: Option<&str>) -> Self {
+        PaneEditor {
+            title: TextEditor::new(title, false),
+            body: body.map(|body| TextEditor::new(body, true)),
+            editing_body: body.is_some(),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        self.title.text()
+    }
+
+    pub fn body(&self) -> Option<String> {
+        self.body.as_ref().map(TextEditor::text)
+    }
+
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        if key == Key::Char('\t') {
+            self.editing_body = !self.editing_body && self.body.is_some();
+            return true;
+        }
+        match &mut self.body {
+            Some(body) if self.editing_body => body.handle_key(key),
+            _ => self.title.handle_key(key),
+        }
+    }
+
+    /// Draws the editor over `area`, replacing the widget drawn there.
+    pub fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::LightYellow));
+        let inner = block.inner(area);
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+        if inner.height == 0 {
+            return;
+        }
+        let active = Style::default().fg(Color::White);
+        let inactive = Style::default().fg(Color::DarkGray);
+        let title_style = if self.editing_body { inactive } else { active.add_modifier(Modifier::BOLD) };
+        self.title.draw(f, Rect::new(inner.x, inner.y, inner.width, 1), title_style, !self.editing_body);
+        if let Some(body) = &self.body {
+            let body_area = Rect::new(inner.x, inner.y + 1, inner.width, inner.height - 1);
+            body.draw(f, body_area, if self.editing_body { active } else { inactive }, self.editing_body);
+        }
+    }
+}
+
+/// Terminal columns taken by `chars`; wide characters take two.
+fn columns(chars: &[char]) -> usize {
+    chars.iter().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+/// Splits a line into rows at most `width` columns wide, breaking after the
+/// last space in a row where possible. Rows are char ranges; there is always
+/// at least one, and every row holds at least one char.
+fn wrap(line: &str, width: usize) -> Vec<(usize, usize)> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    let mut start = 0;
+    while chars.len() - start > 1 && columns(&chars[start..]) > width {
+        // the most chars that fit, but always one
+        let mut fit = start + 1;
+        while fit < chars.len() && columns(&chars[start..fit + 1]) <= width {
+            fit += 1;
+        }
+        let end = chars[start..fit].iter()
+            .rposition(|c| *c == ' ')
+            .map(|space| start + space + 1)
+            .filter(|end| *end > start)
+            .unwrap_or(fit);
+        rows.push((start, end));
+        start = end;
+    }
+    rows.push((start, chars.len()));
+    rows
+}
+
+/// The wrapped row holding column `col`. A column at a row boundary belongs to
+/// the later row.
+fn row_of(rows: &[(usize, usize)], col: usize) -> usize {
+    rows.iter().rposition(|(start, _)| *start <= col).unwrap_or(0)
+}
+
+/// The column `offset` chars into wrapped row `row`, kept inside that row. The
+/// end of every row but the last is the start of the next, so it is excluded.
+fn column_in_row(rows: &[(usize, usize)], row: usize, offset: usize) -> usize {
+    let (start, end) = rows[row];
+    let last = if row + 1 < rows.len() { end - 1 } else { end };
+    start.saturating_add(offset).min(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_text_has_one_row() {
+        let rows = wrap("", 10);
+        assert_eq!(rows, vec![(0, 0)]);
+        assert_eq!(row_of(&rows, 0), 0);
+        assert_eq!(column_in_row(&rows, 0, 5), 0);
+    }
+
+    #[test]
+    fn line_exactly_the_width_is_not_wrapped() {
+        let rows = wrap("abcde", 5);
+        assert_eq!(rows, vec![(0, 5)]);
+        // the cursor after the last char stays on the row
+        assert_eq!(row_of(&rows, 5), 0);
+        assert_eq!(column_in_row(&rows, 0, usize::MAX), 5);
+    }
+
+    #[test]
+    fn wraps_after_the_last_space() {
+        let rows = wrap("hello world", 6);
+        assert_eq!(rows, vec![(0, 6), (6, 11)]);
+        // a row boundary belongs to the later row
+        assert_eq!(row_of(&rows, 6), 1);
+        // the end of a row before another is the next row's start
+        assert_eq!(column_in_row(&rows, 0, usize::MAX), 5);
+        assert_eq!(column_in_row(&rows, 1, usize::MAX), 11);
+    }
+
+    #[test]
+    fn breaks_words_longer_than_the_width() {
+        assert_eq!(wrap("abcdefgh", 3), vec![(0, 3), (3, 6), (6, 8)]);
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        assert_eq!(wrap("日本語テキスト", 6), vec![(0, 3), (3, 6), (6, 7)]);
+        // a wide char that does not fit starts the next row
+        assert_eq!(wrap("ab日本", 3), vec![(0, 2), (2, 3), (3, 4)]);
+        // even a row too narrow for one holds it
+        assert_eq!(wrap("日本", 1), vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn cursor_at_the_end_of_text_moves_between_rows() {
+        let mut editor = TextEditor::new("hello world", false);
+        editor.wrap_width.set(6);
+        editor.handle_key(Key::End);
+        assert_eq!(editor.col, 5);
+        editor.col = 11;
+        editor.handle_key(Key::Up);
+        assert_eq!(editor.col, 5);
+        editor.handle_key(Key::Down);
+        assert_eq!(editor.col, 11);
+        editor.handle_key(Key::Home);
+        assert_eq!(editor.col, 6);
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use tui::Frame;
+use tui::layout::{Alignment, Rect};
+use tui::style::Style;
+use tui::text::Span;
+use tui::widgets::{Block, BorderType, Borders, Paragraph};
+use crate::containers::F;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BorderSet {
+    None,
+    #[default]
+    Plain,
+    Rounded,
+    Double,
+    Thick,
+}
+
+impl BorderSet {
+    /// The next border set, wrapping around. Used to cycle borders from Select mode.
+    pub fn next(self) -> Self {
+        match self {
+            BorderSet::None => BorderSet::Plain,
+            BorderSet::Plain => BorderSet::Rounded,
+            BorderSet::Rounded => BorderSet::Double,
+            BorderSet::Double => BorderSet::Thick,
+            BorderSet::Thick => BorderSet::None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sides {
+    pub top: bool,
+    pub bottom: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl Default for Sides {
+    fn default() -> Self {
+        Sides {
+            top: true,
+            bottom: true,
+            left: true,
+            right: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Spacing {
+    pub top: u16,
+    pub bottom: u16,
+    pub left: u16,
+    pub right: u16,
+}
+
+impl Spacing {
+    pub fn uniform(n: u16) -> Self {
+        Spacing {
+            top: n,
+            bottom: n,
+            left: n,
+            right: n,
+        }
+    }
+
+    /// Shrinks `area` by this spacing, never past an empty rect.
+    pub fn shrink(&self, area: Rect) -> Rect {
+        let width = area.width.saturating_sub(self.left.saturating_add(self.right));
+        let height = area.height.saturating_sub(self.top.saturating_add(self.bottom));
+        Rect {
+            x: area.x.saturating_add(self.left.min(area.width)),
+            y: area.y.saturating_add(self.top.min(area.height)),
+            width,
+            height,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitlePosition {
+    #[default]
+    Top,
+    Bottom,
+}
+
+/// Mirror of `tui::layout::Alignment`, which does not implement serde.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextAlignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl From<TextAlignment> for Alignment {
+    fn from(alignment: TextAlignment) -> Self {
+        match alignment {
+            TextAlignment::Left => Alignment::Left,
+            TextAlignment::Center => Alignment::Center,
+            TextAlignment::Right => Alignment::Right,
+        }
+    }
+}
+
+/// How a widget draws the frame around its content: borders, spacing, title
+/// and footer. Every field has a default so layout files only need to list
+/// what they change.
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FrameOptions {
+    pub border: BorderSet,
+    pub sides: Sides,
+    /// Space between the border and the content.
+    pub padding: Spacing,
+    /// Space between the container's area and the border.
+    pub margin: Spacing,
+    pub title_position: TitlePosition,
+    pub title_alignment: TextAlignment,
+    pub footer: Option<String>,
+    pub footer_alignment: TextAlignment,
+}
+
+impl FrameOptions {
+    fn borders(&self) -> Borders {
+        let mut borders = Borders::NONE;
+        if self.border == BorderSet::None {
+            return borders;
+        }
+        if self.sides.top {
+            borders |= Borders::TOP;
+        }
+        if self.sides.bottom {
+            borders |= Borders::BOTTOM;
+        }
+        if self.sides.left {
+            borders |= Borders::LEFT;
+        }
+        if self.sides.right {
+            borders |= Borders::RIGHT;
+        }
+        borders
+    }
+
+    fn border_type(&self) -> BorderType {
+        match self.border {
+            BorderSet::None | BorderSet::Plain => BorderType::Plain,
+            BorderSet::Rounded => BorderType::Rounded,
+            BorderSet::Double => BorderType::Double,
+            BorderSet::Thick => BorderType::Thick,
+        }
+    }
+
+    /// Draws the frame into `area` and returns the rect left for content.
+    pub fn draw(&self, f: &mut Frame<F>, area: Rect, title: &str, title_style: Style, border_style: Style) -> Rect {
+        let area = self.margin.shrink(area);
+        if area.width == 0 || area.height == 0 {
+            return area;
+        }
+        let mut borders = self.borders();
+        let collapsed = area.height == 1;
+        if collapsed && borders != Borders::NONE {
+            // collapsed: only the title line is visible
+            borders = Borders::TOP;
+        }
+        let title_on_top = self.title_position == TitlePosition::Top || collapsed;
+        let mut block = Block::default()
+            .borders(borders)
+            .border_type(self.border_type())
+            .border_style(border_style);
+        if title_on_top && !title.is_empty() {
+            block = block
+                .title(Span::styled(title.to_string(), title_style))
+                .title_alignment(self.title_alignment.into());
+        }
+        let mut inner = block.inner(area);
+        f.render_widget(block, area);
+
+        let title_at_bottom = !title_on_top && !title.is_empty();
+        if title_at_bottom || self.footer.is_some() {
+            // without a bottom border the title and footer take the last content line
+            let row = if borders.intersects(Borders::BOTTOM) {
+                area.bottom() - 1
+            } else if inner.height > 0 {
+                inner.height -= 1;
+                inner.bottom()
+            } else {
+                return inner;
+            };
+            let line = Rect::new(inner.x, row, inner.width, 1);
+            if let Some(footer) = &self.footer {
+                let footer = Paragraph::new(Span::styled(footer.clone(), border_style))
+                    .alignment(self.footer_alignment.into());
+                f.render_widget(footer, line);
+            }
+            if title_at_bottom {
+                let title = Paragraph::new(Span::styled(title.to_string(), title_style))
+                    .alignment(self.title_alignment.into());
+                f.render_widget(title, line);
+            }
+        }
+        self.padding.shrink(inner)
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use crate::ThingEvent;
+use crate::config::Config;
+use crate::connection::Backoff;
+use crate::mpd::{self, Closer, MpdClient, MpdError};
+
+/// What the idle threads share with the main thread. Partitions are keyed
+/// by name, `None` being the configured one.
+struct Shared {
+    config: Config,
+    /// Bumped on every switch, so a connection made to the old server
+    /// while switching is not used.
+    generation: u64,
+    /// The idle connections, to interrupt or close them.
+    closers: HashMap<Option<String>, Closer>,
+    /// Channels to subscribe to. MPD only tells subscribed connections about
+    /// messages, so the idle connection of the configured partition is the
+    /// one that subscribes.
+    channels: Vec<String>,
+    /// Other partitions panes control, with the token of the thread idling
+    /// on each, so a thread for a partition that was dropped and added again
+    /// knows it was replaced.
+    partitions: HashMap<String, u64>,
+    next_token: u64,
+}
+
+impl Shared {
+    /// Whether the thread for `partition` holding `token` should keep going.
+    fn wants(&self, partition: &Option<String>, token: u64) -> bool {
+        match partition {
+            None => true,
+            Some(partition) => self.partitions.get(partition) == Some(&token),
+        }
+    }
+}
+
+/// Connections waiting in `idle` for changes, one for the configured
+/// partition and one for every other partition a pane controls. `idle`
+/// blocks, so each gets its own thread, and changes are sent to the main
+/// thread as events.
+#[derive(Clone)]
+pub struct IdleServer {
+    shared: Arc<Mutex<Shared>>,
+    events: mpsc::Sender<ThingEvent>,
+}
+
+impl IdleServer {
+    pub fn start(config: Config, events: mpsc::Sender<ThingEvent>) -> Self {
+        let server = IdleServer {
+            shared: Arc::new(Mutex::new(Shared {
+                config,
+                generation: 0,
+                closers: HashMap::new(),
+                channels: Vec::new(),
+                partitions: HashMap::new(),
+                next_token: 0,
+            })),
+            events,
+        };
+        server.spawn(None, 0);
+        server
+    }
+
+    /// Moves every connection to the server of `config`.
+    pub fn switch(&self, config: Config) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.config = config;
+        shared.generation += 1;
+        for (_, closer) in shared.closers.drain() {
+            closer.close();
+        }
+    }
+
+    /// Interrupts `idle` if the channels changed, so the thread subscribes.
+    pub fn set_channels(&self, channels: Vec<String>) {
+        let mut shared = self.shared.lock().unwrap();
+        if channels == shared.channels {
+            return;
+        }
+        shared.channels = channels;
+        if let Some(closer) = shared.closers.get_mut(&None) {
+            closer.noidle();
+        }
+    }
+
+    /// Idles on `partitions` besides the configured one, starting a thread
+    /// for each new partition and closing the connections of the others.
+    pub fn set_partitions(&self, partitions: &[String]) {
+        let mut shared = self.shared.lock().unwrap();
+        let dropped = shared.partitions.keys()
+            .filter(|partition| !partitions.contains(partition))
+            .cloned()
+            .collect::<Vec<String>>();
+        for partition in dropped {
+            shared.partitions.remove(&partition);
+            if let Some(closer) = shared.closers.remove(&Some(partition)) {
+                closer.close();
+            }
+        }
+        for partition in partitions {
+            if !shared.partitions.contains_key(partition) {
+                shared.next_token += 1;
+                let token = shared.next_token;
+                shared.partitions.insert(partition.clone(), token);
+                self.spawn(Some(partition.clone()), token);
+            }
+        }
+    }
+
+    fn spawn(&self, partition: Option<String>, token: u64) {
+        let server = self.clone();
+        thread::spawn(move || server.run(partition, token));
+    }
+
+    /// Keeps an idle connection to `partition` until it is no longer wanted
+    /// or the app exits, reconnecting with backoff.
+    fn run(&self, partition: Option<String>, token: u64) {
+        let mut backoff = Backoff::default();
+        loop {
+            let (config, generation) = {
+                let shared = self.shared.lock().unwrap();
+                if !shared.wants(&partition, token) {
+                    return;
+                }
+                (shared.config.clone(), shared.generation)
+            };
+            let connected = config.connect().and_then(|mut client| {
+                if let Some(partition) = &partition {
+                    client.enter_partition(partition)?;
+                }
+                Ok(client)
+            });
+            let error = match connected {
+                Ok(mut client) => {
+                    {
+                        let mut shared = self.shared.lock().unwrap();
+                        if shared.generation != generation || !shared.wants(&partition, token) {
+                            continue;
+                        }
+                        if let Ok(closer) = client.closer() {
+                            shared.closers.insert(partition.clone(), closer);
+                        }
+                    }
+                    backoff.reset();
+                    match self.listen(&mut client, &partition) {
+                        // the main thread is gone
+                        Ok(()) => return,
+                        Err(err) => err.to_string(),
+                    }
+                }
+                Err(err) => err.to_string(),
+            };
+            {
+                // a switch to another server, or a partition no pane controls
+                // anymore, closes the connection on purpose
+                let shared = self.shared.lock().unwrap();
+                if shared.generation != generation || !shared.wants(&partition, token) {
+                    backoff.reset();
+                    continue;
+                }
+            }
+            // the configured partition speaks for the server; other ones
+            // may simply not exist yet, and are retried quietly
+            if partition.is_none() && self.events.send(ThingEvent::Disconnected(error)).is_err() {
+                return;
+            }
+            backoff.failed();
+            thread::sleep(backoff.remaining());
+        }
+    }
+
+    /// Sends changes until the connection fails. Returns `Ok` once the main
+    /// thread stops taking events.
+    fn listen(&self, client: &mut MpdClient, partition: &Option<String>) -> Result<(), MpdError> {
+        let mut subscribed = Vec::new();
+        let mut subsystems = all_subsystems();
+        loop {
+            if partition.is_none() && subsystems.iter().any(|subsystem| subsystem == "message") {
+                let messages = client.read_messages()?;
+                if !messages.is_empty() && self.events.send(ThingEvent::Messages(messages)).is_err() {
+                    return Ok(());
+                }
+            }
+            if !subsystems.is_empty() && self.events.send(ThingEvent::Idle(partition.clone(), subsystems)).is_err() {
+                return Ok(());
+            }
+            subsystems = match partition {
+                None => self.idle_with_channels(client, &mut subscribed)?,
+                Some(_) => client.idle()?,
+            };
+        }
+    }
+
+    /// Subscribes the idle connection to the channels widgets want, then waits
+    /// for changes. The lock is held until `idle` is sent, so a change to the
+    /// channels either is seen here or interrupts the `idle`.
+    fn idle_with_channels(&self, client: &mut MpdClient, subscribed: &mut Vec<String>) -> Result<Vec<String>, MpdError> {
+        loop {
+            let channels = {
+                let shared = self.shared.lock().unwrap();
+                if shared.channels == *subscribed {
+                    client.start_idle()?;
+                    break;
+                }
+                shared.channels.clone()
+            };
+            let unsubscribe = subscribed.iter()
+                .filter(|channel| !channels.contains(channel))
+                .map(|channel| format!("unsubscribe {}", mpd::quote(channel)));
+            let subscribe = channels.iter()
+                .filter(|channel| !subscribed.contains(channel))
+                .map(|channel| format!("subscribe {}", mpd::quote(channel)));
+            for command in unsubscribe.chain(subscribe).collect::<Vec<String>>() {
+                // a bad channel name only loses that channel
+                match client.command(&command) {
+                    Ok(_) | Err(MpdError::Server { .. }) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            *subscribed = channels;
+        }
+        client.finish_idle()
+    }
+}
+
+/// Every subsystem, as sent after connecting so widgets load their state.
+pub fn all_subsystems() -> Vec<String> {
+    mpd::ALL_SUBSYSTEMS.iter().map(|subsystem| subsystem.to_string()).collect()
+}
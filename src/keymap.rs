@@ -0,0 +1,81 @@
+/// The keys of an input mode, shown in the bottom bar and by `print-keymap`.
+/// The playback keys work in every mode and are listed apart, in
+/// `actions::PLAYBACK_KEYS`.
+pub struct ModeKeys {
+    /// The mode as the bottom bar names it.
+    pub name: &'static str,
+    /// Each key and what it does, most used first as the bar may be cut off.
+    pub keys: &'static [(&'static str, &'static str)],
+}
+
+impl ModeKeys {
+    /// The keys on one line, for the bottom bar.
+    pub fn help(&self) -> String {
+        self.keys.iter()
+            .map(|(key, description)| format!("{}: {}", key, description))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+pub const NORMAL: ModeKeys = ModeKeys {
+    name: "Normal",
+    keys: &[
+        ("q", "Quit"),
+        ("i", "Select mode"),
+        ("z", "Zoom the focused pane"),
+        ("S", "Switch server"),
+    ],
+};
+
+pub const SELECT: ModeKeys = ModeKeys {
+    name: "Select",
+    keys: &[
+        ("q", "Quit"),
+        ("c", "Normal mode"),
+        ("Up/Down/Left/Right", "Navigate"),
+        ("Enter", "Insert mode, to replace the selected pane"),
+        ("z", "Zoom"),
+        ("m", "Collapse"),
+        ("e", "Edit the title and text"),
+        ("b", "Change the border"),
+        ("P", "Change the partition"),
+        ("w", "Save the layout"),
+        ("l", "Reload the layout"),
+        ("Delete", "Clear the selected pane"),
+        ("r", "Clear the whole layout"),
+    ],
+};
+
+pub const INSERT: ModeKeys = ModeKeys {
+    name: "Insert",
+    keys: &[
+        ("q", "Quit"),
+        ("c", "Select mode"),
+        ("Up/Down", "Choose a component"),
+        ("/", "Filter"),
+        ("Enter", "Insert the component"),
+    ],
+};
+
+pub const EDIT: ModeKeys = ModeKeys {
+    name: "Edit",
+    keys: &[
+        ("Tab", "Switch between title and text"),
+        ("Esc", "Save"),
+        ("C-c", "Cancel"),
+    ],
+};
+
+pub const SERVER: ModeKeys = ModeKeys {
+    name: "Server",
+    keys: &[
+        ("q", "Quit"),
+        ("c/Esc", "Normal mode"),
+        ("Up/Down", "Choose a server"),
+        ("Enter", "Switch to it"),
+    ],
+};
+
+/// Every mode, in the order `print-keymap` lists them.
+pub const MODES: &[ModeKeys] = &[NORMAL, SELECT, INSERT, EDIT, SERVER];
@@ -0,0 +1,207 @@
+use std::{fmt, fs, io};
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+use crate::config;
+use crate::containers::{BasicContainer, Container, Pane, RootContainer, Widget};
+use crate::registry::Registry;
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Missing(&'static str),
+    UnknownType(String),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::Io(err) => write!(f, "{}", err),
+            LayoutError::Json(err) => write!(f, "invalid layout: {}", err),
+            LayoutError::Missing(field) => write!(f, "invalid layout: missing field `{}`", field),
+            LayoutError::UnknownType(kind) => write!(f, "invalid layout: unknown type `{}`", kind),
+        }
+    }
+}
+
+impl From<io::Error> for LayoutError {
+    fn from(err: io::Error) -> Self {
+        LayoutError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for LayoutError {
+    fn from(err: serde_json::Error) -> Self {
+        LayoutError::Json(err)
+    }
+}
+
+pub fn default_path() -> PathBuf {
+    config::config_dir().join("layout.json")
+}
+
+pub fn save(root: &dyn Container, path: &Path) -> Result<(), LayoutError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&root.to_value())?)?;
+    Ok(())
+}
+
+/// Loads a layout file. Files whose top level is not a root container are
+/// wrapped in one.
+pub fn load(path: &Path, registry: &Registry) -> Result<Box<dyn Container>, LayoutError> {
+    let value: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if type_of(&value)? == "root" {
+        return container_from_value(&value, registry);
+    }
+    let mut root = RootContainer::new();
+    root.set_child(0, container_from_value(&value, registry)?);
+    Ok(Box::new(root))
+}
+
+/// Builds a container from its layout value. Root and basic containers are
+/// built here, everything else is looked up in the registry.
+pub fn container_from_value(value: &Value, registry: &Registry) -> Result<Box<dyn Container>, LayoutError> {
+    Ok(match type_of(value)? {
+        "root" => Box::new(RootContainer::from_value(value, registry)?),
+        "basic" => Box::new(BasicContainer::from_value(value, registry)?),
+        kind => match registry.container(kind) {
+            Some(kind) => (kind.load)(value, registry)?,
+            None => return Err(LayoutError::UnknownType(kind.to_string())),
+        },
+    })
+}
+
+pub fn widget_from_value(value: &Value, registry: &Registry) -> Result<Box<dyn Widget>, LayoutError> {
+    match registry.widget(type_of(value)?) {
+        Some(kind) => (kind.load)(value, registry),
+        None => Err(LayoutError::UnknownType(type_of(value)?.to_string())),
+    }
+}
+
+/// The two children and split ratio shared by the split containers.
+pub type SplitParts = (Box<dyn Container>, Box<dyn Container>, f32);
+
+pub fn split_from_value(value: &Value, registry: &Registry) -> Result<SplitParts, LayoutError> {
+    let children = value.get("children")
+        .and_then(Value::as_array)
+        .filter(|children| children.len() == 2)
+        .ok_or(LayoutError::Missing("children"))?;
+    let split = value.get("split").and_then(Value::as_f64).unwrap_or(0.5) as f32;
+    Ok((container_from_value(&children[0], registry)?, container_from_value(&children[1], registry)?, split))
+}
+
+pub fn zoomed_from_value(value: &Value) -> bool {
+    value.get("zoomed").and_then(Value::as_bool).unwrap_or(false)
+}
+
+pub fn collapsed_from_value(value: &Value) -> [bool; 2] {
+    let collapsed = |index: usize| value.get("collapsed")
+        .and_then(|collapsed| collapsed.get(index))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    [collapsed(0), collapsed(1)]
+}
+
+/// Checks a layout file as `load` would read it, without building the
+/// widgets in it.
+pub fn check(path: &Path, registry: &Registry) -> Result<(), LayoutError> {
+    let value: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    check_container(&value, registry)
+}
+
+pub fn check_container(value: &Value, registry: &Registry) -> Result<(), LayoutError> {
+    match type_of(value)? {
+        "root" => check_container(value.get("child").ok_or(LayoutError::Missing("child"))?, registry),
+        "basic" => check_widget(value.get("widget").ok_or(LayoutError::Missing("widget"))?, registry),
+        kind => match registry.container(kind) {
+            Some(kind) => (kind.check)(value, registry),
+            None => Err(LayoutError::UnknownType(kind.to_string())),
+        },
+    }
+}
+
+pub fn check_widget(value: &Value, registry: &Registry) -> Result<(), LayoutError> {
+    match registry.widget(type_of(value)?) {
+        Some(kind) => (kind.check)(value),
+        None => Err(LayoutError::UnknownType(type_of(value)?.to_string())),
+    }
+}
+
+pub fn check_split(value: &Value, registry: &Registry) -> Result<(), LayoutError> {
+    let children = value.get("children")
+        .and_then(Value::as_array)
+        .filter(|children| children.len() == 2)
+        .ok_or(LayoutError::Missing("children"))?;
+    children.iter().try_for_each(|child| check_container(child, registry))
+}
+
+/// Checks the fields every widget has, for widgets with no others that can
+/// be wrong.
+pub fn check_pane(value: &Value) -> Result<(), LayoutError> {
+    Pane::from_value(value).map(|_| ())
+}
+
+fn type_of(value: &Value) -> Result<&str, LayoutError> {
+    value.get("type").and_then(Value::as_str).ok_or(LayoutError::Missing("type"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn leaf(widget: Value) -> Value {
+        json!({"type": "basic", "widget": widget})
+    }
+
+    fn split(left: Value, right: Value) -> Value {
+        json!({"type": "hsplit", "children": [left, right]})
+    }
+
+    #[test]
+    fn the_default_layout_checks() {
+        let registry = Registry::default();
+        assert!(check_container(&crate::default_layout().to_value(), &registry).is_ok());
+    }
+
+    #[test]
+    fn structure_errors_are_found() {
+        let registry = Registry::default();
+        let queue = leaf(json!({"type": "queue", "title": "Queue"}));
+        let one_child = json!({"type": "vsplit", "children": [queue.clone()]});
+        assert!(matches!(check_container(&one_child, &registry), Err(LayoutError::Missing("children"))));
+        let unknown = split(queue.clone(), leaf(json!({"type": "clock", "title": "Clock"})));
+        assert!(matches!(check_container(&unknown, &registry), Err(LayoutError::UnknownType(kind)) if kind == "clock"));
+        let no_widget = json!({"type": "root", "child": {"type": "basic"}});
+        assert!(matches!(check_container(&no_widget, &registry), Err(LayoutError::Missing("widget"))));
+        assert!(matches!(check_container(&json!({"child": queue}), &registry), Err(LayoutError::Missing("type"))));
+    }
+
+    #[test]
+    fn widget_fields_are_checked() {
+        let registry = Registry::default();
+        let bad = [
+            json!({"type": "visualizer", "title": "V", "options": {"fps": "fast"}}),
+            json!({"type": "album_art", "title": "A", "options": 3}),
+            json!({"type": "messages", "title": "M", "channels": "news"}),
+            json!({"type": "queue", "title": 7}),
+            json!({"type": "external", "title": "E", "command": "date", "interval_ms": "5000"}),
+            json!({"type": "external", "title": "E", "command": "cat", "stdin": "yes"}),
+            json!({"type": "terminal", "title": "T", "command": ["htop"]}),
+            json!({"type": "script", "title": "S", "script": 1}),
+        ];
+        for widget in bad {
+            assert!(matches!(check_widget(&widget, &registry), Err(LayoutError::Json(_))), "{}", widget);
+        }
+        let good = [
+            json!({"type": "messages", "title": "M", "channels": ["news"]}),
+            json!({"type": "external", "title": "E", "command": "date", "interval_ms": 5000, "stdin": false}),
+            json!({"type": "terminal", "title": "T"}),
+        ];
+        for widget in good {
+            assert!(check_widget(&widget, &registry).is_ok(), "{}", widget);
+        }
+    }
+}
@@ -1,7 +1,10 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+#![allow(clippy::borrowed_box)]
 
-use std::{io, thread};
+use std::{io, process, thread};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::time::Duration;
 use termion::event::*;
@@ -9,30 +12,110 @@ use termion::input::{MouseTerminal, TermRead};
 use termion::raw::{IntoRawMode};
 use tui::backend::TermionBackend;
 use tui::Frame;
-use tui::layout::{Alignment, Rect};
+use tui::layout::Rect;
 use tui::style::{Color, Style};
+use serde_json::{json, Value};
 use tui::widgets::{Block, Borders, Paragraph, Wrap};
-use crate::containers::{BasicContainer, BasicWidget, Container, HSplitContainer, RootContainer, VSplitContainer, WStyleOpt};
+use clap::Parser;
+use crate::actions::Action;
+use crate::cli::Cli;
+use crate::config::{Config, Profile};
+use crate::connection::Connection;
+use crate::control::{Command, ControlSocket, Request};
+use crate::containers::{BasicContainer, BasicWidget, Container, Focus, FocusStyle, HSplitContainer, RootContainer, VSplitContainer, Widget};
+use crate::editor::PaneEditor;
+use crate::menu::{InsertMenu, ProfileMenu};
+use crate::idle::IdleServer;
+use crate::keymap::ModeKeys;
+use crate::mpd::{MpdClient, MpdError};
+use crate::registry::Registry;
+use crate::script::{KeyBindings, Script};
+use crate::stickers::PlayTracker;
 
+mod actions;
+mod ansi;
+mod cli;
+mod config;
+mod connection;
+mod control;
 mod containers;
+mod editor;
+mod frame;
+mod idle;
+mod keymap;
+mod layout;
+mod menu;
+mod mpd;
+mod registry;
+mod script;
+mod stickers;
+mod widgets;
 
 enum ThingEvent {
     Tick,
     Key(Key),
+    /// MPD subsystems that changed in a partition, `None` being the
+    /// configured one.
+    Idle(Option<String>, Vec<String>),
+    /// The idle connection was lost or could not be made.
+    Disconnected(String),
+    /// Messages that arrived on subscribed channels, as channel and message.
+    Messages(Vec<(String, String)>),
+    /// A command from a script on the control socket.
+    Control(Request),
 }
 
 enum InputMode {
     Normal(ContainerStack),
     Select(ContainerStack),
     Insert(ContainerStack),
+    Edit(ContainerStack, PaneEditor),
+    /// Choosing a server profile.
+    Profiles(ContainerStack),
 }
 
-impl Into<ContainerStack> for InputMode {
-    fn into(self) -> ContainerStack {
+impl InputMode {
+    /// The mode's name, as the control socket reports it.
+    fn name(&self) -> &'static str {
+        match self {
+            InputMode::Normal(_) => "normal",
+            InputMode::Select(_) => "select",
+            InputMode::Insert(_) => "insert",
+            InputMode::Edit(..) => "edit",
+            InputMode::Profiles(_) => "profiles",
+        }
+    }
+
+    /// The mode's keys, for the bottom bar.
+    fn keys(&self) -> &'static ModeKeys {
+        match self {
+            InputMode::Normal(_) => &keymap::NORMAL,
+            InputMode::Select(_) => &keymap::SELECT,
+            InputMode::Insert(_) => &keymap::INSERT,
+            InputMode::Edit(..) => &keymap::EDIT,
+            InputMode::Profiles(_) => &keymap::SERVER,
+        }
+    }
+
+    fn stack_mut(&mut self) -> &mut ContainerStack {
         match self {
             InputMode::Normal(stack) => stack,
             InputMode::Select(stack) => stack,
             InputMode::Insert(stack) => stack,
+            InputMode::Edit(stack, _) => stack,
+            InputMode::Profiles(stack) => stack,
+        }
+    }
+}
+
+impl From<InputMode> for ContainerStack {
+    fn from(mode: InputMode) -> Self {
+        match mode {
+            InputMode::Normal(stack) => stack,
+            InputMode::Select(stack) => stack,
+            InputMode::Insert(stack) => stack,
+            InputMode::Edit(stack, _) => stack,
+            InputMode::Profiles(stack) => stack,
         }
     }
 }
@@ -40,6 +123,7 @@ impl Into<ContainerStack> for InputMode {
 struct ContainerStack{
     stack: Vec<u8>,
     root: Box<dyn Container>,
+    focus_style: FocusStyle,
 }
 
 impl ContainerStack {
@@ -47,6 +131,7 @@ impl ContainerStack {
         Self {
             stack: Vec::new(),
             root,
+            focus_style: FocusStyle::default(),
         }
     }
 
@@ -55,96 +140,179 @@ impl ContainerStack {
     }
 
     fn current(&self) -> Option<&Box<dyn Container>> {
-        self.stack.iter().fold(Some(&self.root), |container, id| {
-            match container {
-                Some(container) => container.get_child(*id),
-                None => None,
-            }
-        })
+        self.stack.iter().try_fold(&self.root, |container, id| container.get_child(*id))
     }
 
     fn current_mut(&mut self) -> Option<&mut Box<dyn Container>> {
-        self.stack.iter().fold(Some(&mut self.root), |container, id| {
-            match container {
-                Some(container) => container.get_child_mut(*id),
-                None => None,
-            }
-        })
+        self.stack.iter().try_fold(&mut self.root, |container, id| container.get_child_mut(*id))
     }
 
-    fn set_selected_style(&mut self) {
-        if let Some(container) = self.current_mut() {
-            container.set_override_style(WStyleOpt::default().set_border_style(Style::default().fg(tui::style::Color::Yellow)));
-        }
+    fn set_focus_style(&mut self, style: FocusStyle) {
+        self.root.set_focus_style(style.clone());
+        self.focus_style = style;
     }
 
-    fn unset_selected_style(&mut self) {
+    /// Replaces a child of the current container, carrying the focus theme over
+    /// to the new subtree.
+    fn replace_child(&mut self, index: u8, mut child: Box<dyn Container>) {
+        child.set_focus_style(self.focus_style.clone());
         if let Some(container) = self.current_mut() {
-            container.unset_override_style();
+            container.set_child(index as usize, child);
         }
     }
 
-    fn set_child_selected_style(&mut self, index: u8) {
+    fn clear_focus(&mut self) {
+        self.root.set_focus(Focus::None);
+    }
+
+    /// Marks the current container as focused if it is a leaf, or as the
+    /// parent of the focus otherwise.
+    fn focus_current(&mut self) {
+        self.clear_focus();
         if let Some(container) = self.current_mut() {
-            if let Some(child) = container.get_child_mut(index) {
-                child.set_override_style(WStyleOpt::default().set_border_style(Style::default().fg(tui::style::Color::Yellow)));
+            if container.has_children() {
+                container.set_focus(Focus::Parent);
+            } else {
+                container.set_focus(Focus::Focused);
             }
         }
     }
 
-    fn unset_child_selected_style(&mut self, index: u8) {
+    /// Marks a child of the current container as the Select mode candidate,
+    /// falling back to `focus_current` if there is no such child.
+    fn focus_candidate(&mut self, index: u8) {
+        self.focus_current();
         if let Some(container) = self.current_mut() {
             if let Some(child) = container.get_child_mut(index) {
-                child.unset_override_style();
+                child.set_focus(Focus::Candidate);
             }
         }
     }
 
+    /// Keeps the focus on the current container only if it is a leaf, which
+    /// is the state Normal mode expects.
+    fn focus_leaf(&mut self) {
+        if self.current_has_children() {
+            self.clear_focus();
+        } else {
+            self.focus_current();
+        }
+    }
+
     fn focus_down(&mut self, index: u8) {
-        self.unset_selected_style();
-        if self.current_mut().unwrap().has_children() {
-            if let Some(child) = self.current_mut().unwrap().get_child_mut(index) {
-                self.push(index);
-            }
+        if self.current_has_children() && self.current().unwrap().get_child(index).is_some() {
+            self.push(index);
         }
-        if let Some(container) = self.current_mut() {
-            if container.has_children() {
-                self.set_child_selected_style(0);
-            } else {
-                self.set_selected_style();
-            }
+        if self.current_has_children() {
+            self.focus_candidate(0);
+        } else {
+            self.focus_current();
         }
     }
 
     fn focus_up(&mut self) -> u8 {
-        self.unset_selected_style();
         let ret = if self.stack.len() > 1 {
             // prevents popping root container
             self.stack.pop().unwrap()
         } else {
             0
         };
-        self.set_child_selected_style(ret);
+        self.focus_candidate(ret);
         ret
     }
 
     fn focus_shift(&mut self, cur_index: u8, left: bool) -> bool{
-        self.unset_selected_style();
+        let new_index = if left {
+            cur_index.saturating_sub(1)
+        } else {
+            cur_index + 1
+        };
+        let exists = match self.current() {
+            Some(container) => container.get_child(new_index).is_some(),
+            None => return false,
+        };
+        if exists {
+            self.focus_candidate(new_index);
+        } else {
+            self.focus_current();
+        }
+        exists
+    }
+
+    /// The container Select mode acts on: the candidate child if the current
+    /// container has children, otherwise the current leaf.
+    fn selected_mut(&mut self, index: u8) -> Option<&mut Box<dyn Container>> {
+        if self.current_has_children() {
+            self.current_mut().and_then(|container| container.get_child_mut(index))
+        } else {
+            self.current_mut()
+        }
+    }
+
+    /// Zooms the selected container, or unzooms it if it already was. Only
+    /// one container is zoomed at a time.
+    fn toggle_zoom(&mut self, index: u8) {
+        let zoomed = match self.selected_mut(index) {
+            Some(container) => container.is_zoomed(),
+            None => return,
+        };
+        containers::clear_zoom(&mut self.root);
+        if let Some(container) = self.selected_mut(index) {
+            container.set_zoomed(!zoomed);
+        }
+    }
+
+    /// Zooms or unzooms the focused leaf, as Normal mode does. Returns
+    /// whether it is zoomed now, or `None` if no leaf has focus.
+    fn toggle_zoom_focused(&mut self) -> Option<bool> {
+        if self.current_has_children() {
+            return None;
+        }
+        self.toggle_zoom(0);
+        self.current().map(|container| container.is_zoomed())
+    }
+
+    fn toggle_collapsed(&mut self, index: u8) {
         if let Some(container) = self.current_mut() {
-            let new_index = if left {
-                if cur_index == 0 { 0 } else { cur_index - 1 }
-            } else {
-                cur_index + 1
-            };
-            return if let Some(child) = container.get_child_mut(new_index) {
-                self.set_child_selected_style(new_index);
-                true
-            } else {
-                self.set_selected_style();
-                false
+            let collapsed = container.is_collapsed(index);
+            container.set_collapsed(index, !collapsed);
+        }
+    }
+
+    /// Where the container `selected_mut` returns was last drawn, given the
+    /// area the root was drawn in.
+    fn selected_area(&self, index: u8, area: Rect) -> Option<Rect> {
+        let mut path = self.stack.clone();
+        if self.current_has_children() {
+            path.push(index);
+        }
+        let mut container = &self.root;
+        let mut rect = area;
+        for id in path {
+            rect = *container.child_areas(rect).get(id as usize)?;
+            container = container.get_child(id)?;
+            if container.is_zoomed() {
+                // the root draws a zoomed container over its whole area
+                rect = area;
             }
         }
-        false
+        Some(rect)
+    }
+
+    /// The widget of the current container, if it is a leaf. In Normal mode
+    /// this is the widget that gets keys.
+    fn focused_widget(&self) -> Option<&Box<dyn Widget>> {
+        if self.current_has_children() {
+            return None;
+        }
+        self.current().and_then(|container| container.get_widget())
+    }
+
+    fn focused_widget_mut(&mut self) -> Option<&mut Box<dyn Widget>> {
+        if self.current_has_children() {
+            return None;
+        }
+        self.current_mut().and_then(|container| container.get_widget_mut())
     }
 
     fn current_has_children(&self) -> bool {
@@ -157,63 +325,73 @@ impl ContainerStack {
 }
 
 fn main() {
+    let cli = Cli::parse();
+    let (mut config, mut layout_path, error) = cli.load_config();
+    let registry = Registry::default();
+    if let Some(command) = &cli.command {
+        if let Some(error) = &error {
+            eprintln!("{}", error);
+        }
+        process::exit(cli::run(command, &layout_path, &registry, default_layout));
+    }
+    let mut status = error.unwrap_or_default();
+    let focus_style = match &cli.theme {
+        Some(path) => FocusStyle::load(path).unwrap_or_else(|err| {
+            status = format!("Could not load {}: {}", path.display(), err);
+            config.focus_style.clone()
+        }),
+        None => config.focus_style.clone(),
+    };
+
     let stdin = io::stdin();
 
     let stdout = io::stdout().into_raw_mode().unwrap();
-    let stdout = MouseTerminal::from(stdout);
+    let stdout: Box<dyn Write> = if cli.no_mouse {
+        Box::new(stdout)
+    } else {
+        Box::new(MouseTerminal::from(stdout))
+    };
     let backend = TermionBackend::new(stdout);
     let mut terminal = tui::Terminal::new(backend).unwrap();
 
     terminal.clear().unwrap();
     terminal.hide_cursor().unwrap();
 
-    let mut root_container = RootContainer::new();
-
-    let left_box = String::from("Left");
-    let right_box = String::from("Right");
-    let top_box = String::from("Top");
-    let bottom_box = String::from("");
-
-    root_container.set_child(0, Box::new(HSplitContainer::new(
-        Box::new(VSplitContainer::new(
-            Box::new(BasicContainer::new(
-                Box::new(BasicWidget::new("Top Left".to_string(), "Some Text".to_string())),
-            )),
-            Box::new(BasicContainer::new(
-                Box::new(BasicWidget::new("Bottom Left".to_string(), "Some Text".to_string())),
-
-            )),
-            0.5
-        )),
-            Box::new(VSplitContainer::new(
-                Box::new(BasicContainer::new(
-                    Box::new(BasicWidget::new("Lorem Ipsum".to_string(), top_box))
-                )),
-                Box::new(HSplitContainer::new(
-                    Box::new(BasicContainer::new(
-                        Box::new(BasicWidget::new("Infinite Possibility".to_string(), bottom_box))
-                    )),
-                    Box::new(BasicContainer::new(
-                        Box::new(BasicWidget::new("Death Gripsum".to_string(), right_box))
-                    )),
-                    0.75
-                )),
-                0.15,
-            )),
-            0.15
-        ))
-    );
-    let mut stack = ContainerStack::new(Box::new(root_container));
+    let mut mpd = Connection::default();
+    // widgets are told when the connection goes down, once
+    let mut was_connected = false;
+    let root = load_layout(&layout_path, &registry, &mut status);
+    let mut stack = ContainerStack::new(root);
+    stack.set_focus_style(focus_style);
     stack.push(0);
     let mut input_mode = InputMode::Normal(stack);
-    let mut selection_index = 0 as u8;
-    let mut menu_selection_index = 0 as u8;
+    let mut selection_index: u8 = 0;
+    let mut menu = InsertMenu::default();
+    let mut profile_menu = ProfileMenu::default();
 
-    let events = events(Duration::from_micros(1000000 / 60));
+    let (events_tx, events) = events(Duration::from_micros(1000000 / 60));
+    let idle_server = IdleServer::start(config.clone(), events_tx.clone());
+    script::set_events(events_tx.clone());
+    let (key_bindings, script_errors) = KeyBindings::load();
+    if !script_errors.is_empty() {
+        status = script_errors.join(" | ");
+    }
+    // removes the socket file when the app exits
+    let _control_socket = config.control_socket_path().and_then(|path| {
+        ControlSocket::listen(&path, events_tx)
+            .map_err(|err| status = format!("Could not listen on {}: {}", path.display(), err))
+            .ok()
+    });
 
-    fn draw(stack: &ContainerStack, f: &mut Frame<containers::F>, bottom_text: &str) {
+    fn draw(stack: &ContainerStack, f: &mut Frame<containers::F>, keys: &ModeKeys, connection: &str, status: &str) {
         let area = f.size();
         stack.root.draw(f, Rect::new(0, 0, area.width, area.height - 1));
+        // a status message replaces the key help until the next key press
+        let bottom_text = if status.is_empty() {
+            format!("{} Mode | {} | {}", keys.name, connection, keys.help())
+        } else {
+            format!("{} Mode | {} | {}", keys.name, connection, status)
+        };
         let bottom_bar = Paragraph::new(bottom_text)
             .block(Block::default().borders(Borders::NONE))
             .style(Style::default().fg(Color::White))
@@ -221,73 +399,228 @@ fn main() {
         f.render_widget(bottom_bar, Rect::new(area.x, area.height - 1, area.width, 1));
     }
 
+    // the database update MPD is running, and frames drawn, for the spinner
+    let mut update_job: Option<u32> = None;
+    let mut ticks: usize = 0;
+    // counts plays of songs that finish
+    let mut play_tracker = PlayTracker::default();
+    // the song under the cursor of the focused song list, or the first one
+    let mut song_selection: Option<String> = None;
+
     loop {
         match events.recv().unwrap() {
             ThingEvent::Tick => {
+                ticks = ticks.wrapping_add(1);
+                if was_connected && !mpd.is_connected() {
+                    containers::visit_widgets_mut(&mut input_mode.stack_mut().root, &mut |widget| widget.on_disconnect());
+                }
+                was_connected = mpd.is_connected();
+                let mut channels = Vec::new();
+                let mut partitions = Vec::new();
+                let mut selections = Vec::new();
+                containers::visit_widgets_mut(&mut input_mode.stack_mut().root, &mut |widget| {
+                    channels.extend(widget.channels());
+                    partitions.extend(zone(widget, &config));
+                    selections.extend(widget.selected_song());
+                });
+                let selection = input_mode.stack_mut().focused_widget()
+                    .and_then(|widget| widget.selected_song())
+                    .or_else(|| selections.into_iter().next());
+                if selection != song_selection {
+                    select_song(&mut input_mode.stack_mut().root, &mut mpd, &config, selection.as_deref());
+                    song_selection = selection;
+                }
+                channels.sort();
+                channels.dedup();
+                idle_server.set_channels(channels);
+                partitions.sort();
+                partitions.dedup();
+                idle_server.set_partitions(&partitions);
+                let status = match update_job {
+                    Some(job) if status.is_empty() => format!("Updating database (job {}) {}", job, spinner(ticks)),
+                    Some(job) => format!("Updating database (job {}) {} | {}", job, spinner(ticks), status),
+                    None => status.clone(),
+                };
+                let connection = mpd.describe();
+                let keys = input_mode.keys();
                 terminal.draw(|f| {
                     match &input_mode {
                         InputMode::Normal(container_hierarchy) => {
-                            draw(container_hierarchy, f, "Normal Mode | Press 'q' to quit | Press 'i' to enter insert mode");
+                            draw(container_hierarchy, f, keys, &connection, &status);
                         },
                         InputMode::Select(container_hierarchy) => {
-                            draw(container_hierarchy, f, "Select Mode | Press 'q' to quit | Press 'c' to exit mode | Use arrow keys to navigate | Press ENTER to replace component");
+                            draw(container_hierarchy, f, keys, &connection, &status);
                         },
                         InputMode::Insert(container_heirarchy) => {
-                            draw(container_heirarchy, f, "Insert Mode | Press 'q' to quit | Press 'c' to exit mode | Use arrow keys to navigate | Press ENTER to insert component");
+                            draw(container_heirarchy, f, keys, &connection, &status);
+                            menu.draw(f, &registry);
+                        }
+                        InputMode::Edit(container_hierarchy, editor) => {
+                            draw(container_hierarchy, f, keys, &connection, &status);
                             let area = f.size();
-                            let context_menu = Paragraph::new("Insert Mode")
-                                .block(Block::default().borders(tui::widgets::Borders::ALL))
-                                .style(Style::default().fg(tui::style::Color::White))
-                                .wrap(Wrap { trim: true })
-                                .alignment(tui::layout::Alignment::Center);
-                            // create a list of possible containers and widgets
-                            let items = vec!["Horizontal Split Container", "Vertical Split Container", "Basic Widget"];
-                            let mut index = 0;
-                            let menu_items = items.iter().map(|text| {
-                                let out = Paragraph::new(*text)
-                                    .block(Block::default().borders(tui::widgets::Borders::NONE))
-                                    .style(
-                                        if index == menu_selection_index as usize {
-                                            Style::default().fg(tui::style::Color::Yellow)
-                                        } else {
-                                            Style::default().fg(tui::style::Color::White)
-                                        }
-                                    )
-                                    .wrap(Wrap { trim: true })
-                                    .alignment(tui::layout::Alignment::Center);
-                                index += 1;
-                                return out;
-                            }).collect::<Vec<Paragraph>>();
-                            f.render_widget(context_menu, Rect::new(area.width / 2 - 10, area.height / 2 - 2, 20, 2 + items.len() as u16));
-                            index = 0;
-                            for item in menu_items {
-                                f.render_widget(item, Rect::new(area.width / 2 - 10, area.height / 2 - 1 + index as u16, 20, 1));
-                                index += 1;
+                            let root_area = Rect::new(0, 0, area.width, area.height - 1);
+                            if let Some(rect) = container_hierarchy.selected_area(selection_index, root_area) {
+                                editor.draw(f, rect);
                             }
                         }
+                        InputMode::Profiles(container_hierarchy) => {
+                            draw(container_hierarchy, f, keys, &connection, &status);
+                            profile_menu.draw(f, &config);
+                        }
                     }
                 }).unwrap();
             },
+            ThingEvent::Idle(partition, subsystems) => {
+                notify_widgets(&mut input_mode.stack_mut().root, &mut mpd, &config, partition.as_deref(), &subsystems);
+                if partition.is_some() {
+                    continue;
+                }
+                // the idle thread got through, so the server is back
+                mpd.retry_now();
+                if subsystems.iter().any(|subsystem| subsystem == "player") {
+                    count_play(&mut play_tracker, &mut mpd, &config);
+                }
+                if subsystems.iter().any(|subsystem| subsystem == "update") {
+                    let job = mpd.client(&config)
+                        .and_then(|client| client.status())
+                        .ok()
+                        .and_then(|status| status.parse::<u32>("updating_db"));
+                    if let (Some(finished), None) = (update_job, job) {
+                        status = format!("Database update finished (job {})", finished);
+                    }
+                    update_job = job;
+                }
+            }
+            ThingEvent::Disconnected(error) => {
+                mpd.lost(error);
+                play_tracker = PlayTracker::default();
+            }
+            ThingEvent::Messages(messages) => {
+                containers::visit_widgets_mut(&mut input_mode.stack_mut().root, &mut |widget| {
+                    for (channel, message) in &messages {
+                        widget.on_message(channel, message);
+                    }
+                });
+            }
+            ThingEvent::Control(request) => {
+                match request.command.clone() {
+                    Command::State => {
+                        let mode = input_mode.name();
+                        let state = describe_state(input_mode.stack_mut(), mode, &mpd, &config, &layout_path, &status);
+                        request.reply_state(state);
+                    }
+                    Command::Action(action) => {
+                        let partition = input_mode.stack_mut().focused_widget().and_then(|widget| zone(widget, &config));
+                        let result = run_action(action, &mut mpd, &config, partition.as_deref());
+                        status = result.clone().unwrap_or_else(|err| err);
+                        request.reply(result);
+                    }
+                    _ if !matches!(input_mode, InputMode::Normal(_) | InputMode::Select(_)) => {
+                        // leaving for Normal mode would throw away what is
+                        // being edited or picked
+                        let error = format!("busy in {} mode, finish or cancel it first", input_mode.name());
+                        request.reply(Err(error));
+                    }
+                    command => {
+                        // the other commands act on the layout, from Normal mode
+                        let mut stack = ContainerStack::from(input_mode);
+                        selection_index = 0;
+                        let result = match command {
+                            Command::Focus(pane) => focus_pane(&mut stack, &pane),
+                            Command::Zoom(pane) => {
+                                match pane {
+                                    Some(pane) => focus_pane(&mut stack, &pane).map(|_| ()),
+                                    None => Ok(()),
+                                }.and_then(|_| match stack.toggle_zoom_focused() {
+                                    Some(zoomed) => Ok(String::from(if zoomed { "Zoomed" } else { "Unzoomed" })),
+                                    None => Err(String::from("no pane has focus")),
+                                })
+                            }
+                            Command::LoadLayout(path) => {
+                                let path = path.map(|path| config::expand_home(&path)).unwrap_or_else(|| layout_path.clone());
+                                match layout::load(&path, &registry) {
+                                    Ok(root) => {
+                                        stack.root = root;
+                                        stack.root.set_focus_style(stack.focus_style.clone());
+                                        refresh_widgets(&mut stack.root, &mut mpd, &config);
+                                        stack.stack = vec![0];
+                                        stack.focus_leaf();
+                                        let message = format!("Loaded layout from {}", path.display());
+                                        // saving writes back to the file loaded
+                                        layout_path = path;
+                                        Ok(message)
+                                    }
+                                    Err(err) => Err(format!("Could not load {}: {}", path.display(), err)),
+                                }
+                            }
+                            Command::Profile(name) => match config.find_profile(&name).cloned() {
+                                Some(profile) => {
+                                    let (new_stack, message) = switch_profile(&profile, &mut config, &mut layout_path, &registry, &stack.focus_style, &idle_server, &mut mpd);
+                                    stack = new_stack;
+                                    play_tracker = PlayTracker::default();
+                                    Ok(message)
+                                }
+                                None => Err(format!("No profile named {}", name)),
+                            },
+                            Command::State | Command::Action(_) => unreachable!(),
+                        };
+                        input_mode = InputMode::Normal(stack);
+                        status = result.clone().unwrap_or_else(|err| err);
+                        request.reply(result);
+                    }
+                }
+            }
             ThingEvent::Key(key) => {
+                status.clear();
+                let typing = match &input_mode {
+                    InputMode::Insert(_) => menu.is_filtering(),
+                    InputMode::Edit(..) => true,
+                    InputMode::Normal(x) => x.focused_widget().is_some_and(|widget| widget.is_editing()),
+                    _ => false,
+                };
+                if let Some(action) = actions::playback_action(key).filter(|_| !typing) {
+                    // playback keys control the partition of the focused pane
+                    let partition = input_mode.stack_mut().focused_widget().and_then(|widget| zone(widget, &config));
+                    status = run_action(action, &mut mpd, &config, partition.as_deref()).unwrap_or_else(|err| err);
+                    continue;
+                }
                 match input_mode {
                     InputMode::Normal(mut x) => {
+                        if typing {
+                            status = widget_key(&mut x, key, &mut mpd, &config);
+                            input_mode = InputMode::Normal(x);
+                            continue;
+                        }
                         match key {
                             Key::Char('q') => break,
                             Key::Char('i') => {
-                                x.set_selected_style();
+                                x.focus_current();
                                 input_mode = InputMode::Select(x);
                                 continue;
                             }
-                            _ => {}
+                            Key::Char('z') => {
+                                x.toggle_zoom_focused();
+                            }
+                            Key::Char('S') => {
+                                profile_menu.reset(&config);
+                                input_mode = InputMode::Profiles(x);
+                                continue;
+                            }
+                            _ => {
+                                status = match key_bindings.find(key) {
+                                    Some((script, function)) => run_script_action(script, function, &mut x, &mut mpd, &config, &layout_path, &status),
+                                    None => widget_key(&mut x, key, &mut mpd, &config),
+                                };
+                            }
                         }
                         input_mode = InputMode::Normal(x);
                     }
                     InputMode::Select(mut x) => {
-                        x.set_child_selected_style(selection_index);
+                        x.focus_candidate(selection_index);
                         match key {
                             Key::Char('q') => break,
                             Key::Char('c') => {
-                                x.root.unset_override_style();
+                                x.focus_leaf();
                                 input_mode = InputMode::Normal(x);
                                 continue;
                             }
@@ -298,25 +631,71 @@ fn main() {
                             Key::Up => {
                                 selection_index = x.focus_up();
                             }
-                            Key::Left => {
-                                if x.focus_shift(selection_index, true) {
-                                    selection_index = selection_index.saturating_sub(1);
-                                }
+                            Key::Left if x.focus_shift(selection_index, true) => {
+                                selection_index = selection_index.saturating_sub(1);
                             }
-                            Key::Right => {
-                                if x.focus_shift(selection_index, false) {
-                                    selection_index = selection_index.saturating_add(1);
-                                }
+                            Key::Right if x.focus_shift(selection_index, false) => {
+                                selection_index = selection_index.saturating_add(1);
                             }
                             Key::Delete => {
-                                x.current_mut().unwrap().set_child(selection_index as usize, Box::new(BasicContainer::default()));
+                                x.replace_child(selection_index, Box::new(BasicContainer::default()));
                             }
                             Key::Char('r') => {
-                                x.root.set_child(0, Box::new(BasicContainer::default()));
+                                let mut child: Box<dyn Container> = Box::new(BasicContainer::default());
+                                child.set_focus_style(x.focus_style.clone());
+                                x.root.set_child(0, child);
                                 x.stack = vec![0];
                             }
+                            Key::Char('z') => {
+                                x.toggle_zoom(selection_index);
+                            }
+                            Key::Char('m') => {
+                                x.toggle_collapsed(selection_index);
+                            }
+                            Key::Char('e') => {
+                                let editor = x.selected_mut(selection_index)
+                                    .and_then(|container| container.get_widget())
+                                    .map(|widget| PaneEditor::new(&widget.get_title(), widget.get_text().as_deref()));
+                                if let Some(editor) = editor {
+                                    input_mode = InputMode::Edit(x, editor);
+                                    continue;
+                                }
+                            }
+                            Key::Char('b') => {
+                                if let Some(frame) = x.selected_mut(selection_index)
+                                    .and_then(|container| container.get_widget_mut())
+                                    .and_then(|widget| widget.get_frame_mut()) {
+                                    frame.border = frame.border.next();
+                                }
+                            }
+                            Key::Char('P') => {
+                                if let Some(widget) = x.selected_mut(selection_index).and_then(|container| container.get_widget_mut()) {
+                                    status = next_partition(widget, &mut mpd, &config);
+                                    refresh_widget(widget, &mut mpd, &config);
+                                }
+                            }
+                            Key::Char('w') => {
+                                status = match layout::save(x.root.as_ref(), &layout_path) {
+                                    Ok(()) => format!("Saved layout to {}", layout_path.display()),
+                                    Err(err) => format!("Could not save {}: {}", layout_path.display(), err),
+                                };
+                            }
+                            Key::Char('l') => {
+                                match layout::load(&layout_path, &registry) {
+                                    Ok(root) => {
+                                        x.root = root;
+                                        x.root.set_focus_style(x.focus_style.clone());
+                                        refresh_widgets(&mut x.root, &mut mpd, &config);
+                                        x.stack = vec![0];
+                                        selection_index = 0;
+                                        x.focus_candidate(selection_index);
+                                        status = format!("Loaded layout from {}", layout_path.display());
+                                    }
+                                    Err(err) => status = format!("Could not load {}: {}", layout_path.display(), err),
+                                }
+                            }
                             Key::Char('\n') => {
-                                menu_selection_index = 0;
+                                menu.reset();
                                 input_mode = InputMode::Insert(x);
                                 continue;
                             }
@@ -325,6 +704,11 @@ fn main() {
                         input_mode = InputMode::Select(x);
                     }
                     InputMode::Insert(mut x) => {
+                        if menu.is_filtering() {
+                            menu.filter_key(key);
+                            input_mode = InputMode::Insert(x);
+                            continue;
+                        }
                         match key {
                             Key::Char('q') => break,
                             Key::Char('c') => {
@@ -333,23 +717,18 @@ fn main() {
                                 continue;
                             }
                             Key::Down => {
-                                menu_selection_index = menu_selection_index.saturating_add(1);
+                                menu.next(&registry);
                             }
                             Key::Up => {
-                                menu_selection_index = menu_selection_index.saturating_sub(1);
+                                menu.previous();
+                            }
+                            Key::Char('/') => {
+                                menu.start_filter();
                             }
                             Key::Char('\n') => {
-                                match menu_selection_index {
-                                    0 => {
-                                        x.current_mut().unwrap().set_child(selection_index as usize, Box::new(HSplitContainer::default()));
-                                    }
-                                    1 => {
-                                        x.current_mut().unwrap().set_child(selection_index as usize, Box::new(VSplitContainer::default()));
-                                    }
-                                    2 => {
-                                        x.current_mut().unwrap().set_child(selection_index as usize, Box::new(BasicContainer::default()));
-                                    }
-                                    _ => {}
+                                if let Some(entry) = menu.selected(&registry) {
+                                    x.replace_child(selection_index, entry.create());
+                                    refresh_widgets(&mut x.root, &mut mpd, &config);
                                 }
                                 input_mode = InputMode::Insert(x);
                                 selection_index = 0;
@@ -359,6 +738,54 @@ fn main() {
                         }
                         input_mode = InputMode::Insert(x);
                     }
+                    InputMode::Edit(mut x, mut editor) => {
+                        match key {
+                            Key::Esc => {
+                                if let Some(widget) = x.selected_mut(selection_index).and_then(|container| container.get_widget_mut()) {
+                                    widget.set_title(editor.title());
+                                    if let Some(body) = editor.body() {
+                                        widget.set_text(body);
+                                    }
+                                }
+                                input_mode = InputMode::Select(x);
+                                continue;
+                            }
+                            Key::Ctrl('c') => {
+                                input_mode = InputMode::Select(x);
+                                continue;
+                            }
+                            key => {
+                                editor.handle_key(key);
+                            }
+                        }
+                        input_mode = InputMode::Edit(x, editor);
+                    }
+                    InputMode::Profiles(x) => {
+                        match key {
+                            Key::Char('q') => break,
+                            Key::Char('c') | Key::Esc => {
+                                input_mode = InputMode::Normal(x);
+                                continue;
+                            }
+                            Key::Down => {
+                                profile_menu.next(&config);
+                            }
+                            Key::Up => {
+                                profile_menu.previous();
+                            }
+                            Key::Char('\n') => {
+                                if let Some(profile) = profile_menu.selected(&config).cloned() {
+                                    let (stack, message) = switch_profile(&profile, &mut config, &mut layout_path, &registry, &x.focus_style, &idle_server, &mut mpd);
+                                    play_tracker = PlayTracker::default();
+                                    status = message;
+                                    input_mode = InputMode::Normal(stack);
+                                    continue;
+                                }
+                            }
+                            _ => {}
+                        }
+                        input_mode = InputMode::Profiles(x);
+                    }
                 }
             }
         }
@@ -367,9 +794,281 @@ fn main() {
     terminal.clear().unwrap();
 }
 
-fn events(tick_rate: Duration) -> mpsc::Receiver<ThingEvent> {
+/// Runs a transport action in `partition`, connecting first if needed, and
+/// returns a message for the bottom bar. A lost connection is dropped so the
+/// next action reconnects.
+fn run_action(action: Action, mpd: &mut Connection, config: &Config, partition: Option<&str>) -> Result<String, String> {
+    let client = match mpd.client(config) {
+        Ok(client) => client,
+        Err(err) => return Err(format!("Could not connect to MPD: {}", err)),
+    };
+    let result = client.enter_partition(partition.unwrap_or(config.partition_name()))
+        .and_then(|_| action.run(client, config));
+    match mpd.check(result) {
+        Ok(message) => Ok(message),
+        Err(MpdError::Io(err)) => Err(format!("{}: connection lost: {}", action.name(), err)),
+        Err(err) => Err(format!("{}: {}", action.name(), err)),
+    }
+}
+
+/// Runs a script function bound to a key with the app's state, in the
+/// partition of the focused pane. Returns what it returned or printed, for
+/// the bottom bar.
+fn run_script_action(script: &Script, function: &str, stack: &mut ContainerStack, mpd: &mut Connection, config: &Config, layout_path: &Path, status: &str) -> String {
+    let state = script::to_dynamic(&describe_state(stack, "normal", mpd, config, layout_path, status));
+    let partition = stack.focused_widget().and_then(|widget| zone(widget, config));
+    let client = mpd.client(config)
+        .and_then(|client| enter_zone(client, partition.as_deref(), config))
+        .ok();
+    let result = script.call(function, vec![state], client);
+    let printed = script.take_printed();
+    match result {
+        Ok(result) if result.is_string() => result.to_string(),
+        Ok(_) => printed.unwrap_or_default(),
+        Err(err) => err,
+    }
+}
+
+/// Switches to the server and layout of `profile`. Returns the new layout
+/// and a message for the bottom bar.
+fn switch_profile(profile: &Profile, config: &mut Config, layout_path: &mut PathBuf, registry: &Registry, focus_style: &FocusStyle, idle_server: &IdleServer, mpd: &mut Connection) -> (ContainerStack, String) {
+    config.use_profile(profile);
+    *layout_path = profile.layout_path();
+    let mut layout_error = String::new();
+    let mut stack = ContainerStack::new(load_layout(layout_path, registry, &mut layout_error));
+    stack.set_focus_style(focus_style.clone());
+    stack.push(0);
+    idle_server.switch(config.clone());
+    mpd.reset();
+    let mut status = match mpd.client(config) {
+        Ok(_) => format!("Connected to {}", profile.name),
+        Err(err) => format!("Could not connect to {}: {}", profile.name, err),
+    };
+    if !layout_error.is_empty() {
+        status = format!("{} | {}", status, layout_error);
+    }
+    (stack, status)
+}
+
+/// Focuses the pane titled `pane`, or else the first one of that widget
+/// type, as if it had been picked in Select mode.
+fn focus_pane(stack: &mut ContainerStack, pane: &str) -> Result<String, String> {
+    let path = containers::find_widget(&stack.root, &|widget| widget.get_title() == pane)
+        .or_else(|| containers::find_widget(&stack.root, &|widget| widget.to_value()["type"] == pane))
+        .ok_or_else(|| format!("No pane named {}", pane))?;
+    stack.stack = path;
+    stack.focus_leaf();
+    Ok(format!("Focused {}", pane))
+}
+
+/// What the control socket's `state` command reports.
+fn describe_state(stack: &mut ContainerStack, mode: &str, mpd: &Connection, config: &Config, layout_path: &Path, status: &str) -> Value {
+    let pane = |widget: &Box<dyn Widget>| json!({
+        "title": widget.get_title(),
+        "type": widget.to_value()["type"],
+        "partition": widget.pane().partition.as_deref().unwrap_or(config.partition_name()),
+    });
+    let focused = stack.focused_widget().map(pane);
+    let zoomed = containers::find_zoomed(&stack.root)
+        .and_then(|container| container.get_widget())
+        .map(|widget| widget.get_title());
+    let mut panes = Vec::new();
+    containers::visit_widgets_mut(&mut stack.root, &mut |widget| panes.push(pane(widget)));
+    json!({
+        "mode": mode,
+        "connected": mpd.is_connected(),
+        "connection": mpd.describe(),
+        "profile": config.profile,
+        "layout": layout_path,
+        "status": status,
+        "focused": focused,
+        "zoomed": zoomed,
+        "panes": panes,
+    })
+}
+
+/// Passes a key to the focused widget, connecting first if needed. Returns
+/// a message for the bottom bar.
+fn widget_key(stack: &mut ContainerStack, key: Key, mpd: &mut Connection, config: &Config) -> String {
+    let widget = match stack.focused_widget_mut() {
+        Some(widget) => widget,
+        None => return String::new(),
+    };
+    let partition = zone(widget, config);
+    let (client, error) = match mpd.client(config).and_then(|client| enter_zone(client, partition.as_deref(), config)) {
+        Ok(client) => (Some(client), None),
+        Err(err) => (None, Some(err)),
+    };
+    let result = widget.handle_key(key, client, config);
+    // a widget without a connection can only say so, not why
+    let result = match (result, error) {
+        (Err(_), Some(err)) => Err(err),
+        (result, _) => result,
+    };
+    match mpd.check(result) {
+        Ok(_) => widget.take_status().unwrap_or_default(),
+        Err(MpdError::Io(err)) => format!("Connection lost: {}", err),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// Loads the layout at `path`. A profile without a layout of its own gets
+/// the shared one, and without any file the built-in layout is used. Errors
+/// for files that exist but cannot be read go to `status`.
+fn load_layout(path: &Path, registry: &Registry, status: &mut String) -> Box<dyn Container> {
+    let mut paths = vec![path.to_path_buf()];
+    if path != layout::default_path() {
+        paths.push(layout::default_path());
+    }
+    for path in paths {
+        match layout::load(&path, registry) {
+            Ok(root) => return root,
+            Err(layout::LayoutError::Io(err)) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                *status = format!("Could not load {}: {}", path.display(), err);
+                break;
+            }
+        }
+    }
+    default_layout()
+}
+
+/// Passes MPD subsystems that changed in `partition` to the widgets that
+/// control it, connecting first if needed.
+fn notify_widgets(root: &mut Box<dyn Container>, mpd: &mut Connection, config: &Config, partition: Option<&str>, subsystems: &[String]) {
+    if let Ok(client) = mpd.client(config) {
+        containers::visit_widgets_mut(root, &mut |widget| {
+            if zone(widget, config).as_deref() == partition && enter_zone(client, partition, config).is_ok() {
+                widget.on_idle(client, config, subsystems);
+            }
+        });
+    }
+}
+
+/// Has every widget load its state, in the partition it controls.
+fn refresh_widgets(root: &mut Box<dyn Container>, mpd: &mut Connection, config: &Config) {
+    containers::visit_widgets_mut(root, &mut |widget| refresh_widget(widget, mpd, config));
+}
+
+/// Has a widget load its state, in the partition it controls.
+fn refresh_widget(widget: &mut Box<dyn Widget>, mpd: &mut Connection, config: &Config) {
+    if let Ok(client) = mpd.client(config) {
+        if enter_zone(client, zone(widget, config).as_deref(), config).is_ok() {
+            widget.on_idle(client, config, &idle::all_subsystems());
+        }
+    }
+}
+
+/// Tells every widget which song is selected, in the partition it controls.
+fn select_song(root: &mut Box<dyn Container>, mpd: &mut Connection, config: &Config, file: Option<&str>) {
+    if let Ok(client) = mpd.client(config) {
+        containers::visit_widgets_mut(root, &mut |widget| {
+            if enter_zone(client, zone(widget, config).as_deref(), config).is_ok() {
+                widget.on_song_selected(client, config, file);
+            }
+        });
+    }
+}
+
+/// Moves a widget's pane to the next partition on the server and returns a
+/// message for the bottom bar.
+fn next_partition(widget: &mut Box<dyn Widget>, mpd: &mut Connection, config: &Config) -> String {
+    let result = mpd.client(config).and_then(|client| client.partitions());
+    let partitions = match mpd.check(result) {
+        Ok(partitions) => partitions,
+        Err(err) => return format!("Could not list partitions: {}", err),
+    };
+    let current = zone(widget, config).unwrap_or_else(|| config.partition_name().to_string());
+    let next = partitions.iter()
+        .position(|partition| *partition == current)
+        .and_then(|index| partitions.get(index + 1))
+        .or(partitions.first())
+        .cloned()
+        .unwrap_or(current);
+    let message = format!("Pane controls partition {}", next);
+    widget.pane_mut().partition = Some(next).filter(|next| next != config.partition_name());
+    message
+}
+
+/// The partition a widget's pane controls, or `None` for the configured one.
+fn zone(widget: &Box<dyn Widget>, config: &Config) -> Option<String> {
+    widget.pane().partition.clone().filter(|partition| partition != config.partition_name())
+}
+
+/// Moves `client` into `partition`, or the configured one for `None`.
+fn enter_zone<'a>(client: &'a mut MpdClient, partition: Option<&str>, config: &Config) -> Result<&'a mut MpdClient, MpdError> {
+    client.enter_partition(partition.unwrap_or(config.partition_name()))?;
+    Ok(client)
+}
+
+/// Adds a play to the song that just finished, if one did. Servers without
+/// a sticker database reject this, which is not worth reporting.
+fn count_play(tracker: &mut PlayTracker, mpd: &mut Connection, config: &Config) {
+    let client = match mpd.client(config) {
+        Ok(client) => client,
+        Err(_) => return,
+    };
+    let result = enter_zone(client, None, config)
+        .and_then(|client| tracker.update(client))
+        .and_then(|finished| match finished {
+            Some(uri) => stickers::count_play(client, &uri).map(|_| ()),
+            None => Ok(()),
+        });
+    let _ = mpd.check(result);
+}
+
+/// A frame of a spinner that turns every few ticks.
+fn spinner(ticks: usize) -> char {
+    const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+    FRAMES[ticks / 6 % FRAMES.len()]
+}
+
+fn default_layout() -> Box<dyn Container> {
+    let mut root_container = RootContainer::new();
+
+    let left_box = String::from("Left");
+    let right_box = String::from("Right");
+    let top_box = String::from("Top");
+    let bottom_box = String::from("");
+
+    root_container.set_child(0, Box::new(HSplitContainer::new(
+        Box::new(VSplitContainer::new(
+            Box::new(BasicContainer::new(
+                Box::new(BasicWidget::new("Top Left".to_string(), "Some Text".to_string())),
+            )),
+            Box::new(BasicContainer::new(
+                Box::new(BasicWidget::new("Bottom Left".to_string(), "Some Text".to_string())),
+
+            )),
+            0.5
+        )),
+            Box::new(VSplitContainer::new(
+                Box::new(BasicContainer::new(
+                    Box::new(BasicWidget::new("Lorem Ipsum".to_string(), top_box))
+                )),
+                Box::new(HSplitContainer::new(
+                    Box::new(BasicContainer::new(
+                        Box::new(BasicWidget::new("Infinite Possibility".to_string(), bottom_box))
+                    )),
+                    Box::new(BasicContainer::new(
+                        Box::new(BasicWidget::new("Death Gripsum".to_string(), right_box))
+                    )),
+                    0.75
+                )),
+                0.15,
+            )),
+            0.15
+        ))
+    );
+    Box::new(root_container)
+}
+
+/// Starts the threads sending key presses and ticks. Returns the channel
+/// for other threads to send events on, and the end the app reads from.
+fn events(tick_rate: Duration) -> (mpsc::Sender<ThingEvent>, mpsc::Receiver<ThingEvent>) {
     let (tx, rx) = mpsc::channel();
     let keys_tx = tx.clone();
+    let events_tx = tx.clone();
     thread::spawn(move || {
         let stdin = io::stdin();
         for key in stdin.keys().flatten() {
@@ -386,5 +1085,5 @@ fn events(tick_rate: Duration) -> mpsc::Receiver<ThingEvent> {
         }
         thread::sleep(tick_rate);
     });
-    return rx;
-}
\ No newline at end of file
+    (events_tx, rx)
+}
@@ -0,0 +1,180 @@
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::Rect;
+use tui::style::{Color, Modifier, Style};
+use tui::text::Span;
+use tui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph};
+use crate::config::{Config, Profile};
+use crate::containers::F;
+use crate::registry::{Entry, Registry};
+
+const MENU_WIDTH: u16 = 44;
+
+/// State of the Insert mode menu: the selected row, scroll offset and filter.
+#[derive(Default)]
+pub struct InsertMenu {
+    state: ListState,
+    filter: String,
+    filtering: bool,
+}
+
+impl InsertMenu {
+    pub fn reset(&mut self) {
+        *self = InsertMenu::default();
+        self.state.select(Some(0));
+    }
+
+    pub fn is_filtering(&self) -> bool {
+        self.filtering
+    }
+
+    pub fn start_filter(&mut self) {
+        self.filtering = true;
+    }
+
+    /// Edits the filter. Enter or Esc stop editing, keeping the filter.
+    pub fn filter_key(&mut self, key: Key) {
+        match key {
+            Key::Char('\n') | Key::Esc => self.filtering = false,
+            Key::Backspace => {
+                self.filter.pop();
+            }
+            Key::Char(c) => self.filter.push(c),
+            _ => return,
+        }
+        self.state.select(Some(0));
+    }
+
+    pub fn next(&mut self, registry: &Registry) {
+        let len = registry.entries(&self.filter).len();
+        let index = self.state.selected().unwrap_or(0);
+        self.state.select(Some((index + 1).min(len.saturating_sub(1))));
+    }
+
+    pub fn previous(&mut self) {
+        let index = self.state.selected().unwrap_or(0);
+        self.state.select(Some(index.saturating_sub(1)));
+    }
+
+    pub fn selected<'a>(&self, registry: &'a Registry) -> Option<Entry<'a>> {
+        let index = self.state.selected()?;
+        registry.entries(&self.filter).into_iter().nth(index)
+    }
+
+    pub fn draw(&mut self, f: &mut Frame<F>, registry: &Registry) {
+        let entries = registry.entries(&self.filter);
+        let area = f.size();
+        // filter line, description line and borders around the list
+        let height = (entries.len().max(1) as u16 + 4).min(area.height.saturating_sub(1));
+        let width = MENU_WIDTH.min(area.width);
+        let rect = Rect::new((area.width - width) / 2, (area.height - height) / 2, width, height);
+        if rect.height < 5 {
+            return;
+        }
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::White))
+            .title(Span::styled("Insert", Style::default().fg(Color::White)));
+        let inner = block.inner(rect);
+        f.render_widget(Clear, rect);
+        f.render_widget(block, rect);
+
+        let filter = if self.filtering || !self.filter.is_empty() {
+            Span::styled(format!("/{}", self.filter), Style::default().fg(Color::White))
+        } else {
+            Span::styled("Press '/' to filter", Style::default().fg(Color::DarkGray))
+        };
+        f.render_widget(Paragraph::new(filter), Rect::new(inner.x, inner.y, inner.width, 1));
+
+        let description = self.state.selected()
+            .and_then(|index| entries.get(index))
+            .map(Entry::description)
+            .unwrap_or("");
+        f.render_widget(
+            Paragraph::new(Span::styled(description, Style::default().fg(Color::DarkGray))),
+            Rect::new(inner.x, inner.bottom() - 1, inner.width, 1),
+        );
+
+        let list_area = Rect::new(inner.x, inner.y + 1, inner.width, inner.height - 2);
+        if entries.is_empty() {
+            let message = Span::styled("No matches", Style::default().fg(Color::DarkGray));
+            f.render_widget(Paragraph::new(message), list_area);
+            return;
+        }
+        let items = entries.iter()
+            .map(|entry| ListItem::new(entry.name()))
+            .collect::<Vec<ListItem>>();
+        let list = List::new(items)
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            .highlight_symbol("> ");
+        f.render_stateful_widget(list, list_area, &mut self.state);
+    }
+}
+
+/// State of the server profile picker.
+#[derive(Default)]
+pub struct ProfileMenu {
+    state: ListState,
+}
+
+impl ProfileMenu {
+    /// Starts with the active profile selected.
+    pub fn reset(&mut self, config: &Config) {
+        let active = config.profile.as_ref()
+            .and_then(|name| config.profiles.iter().position(|profile| &profile.name == name));
+        self.state.select(Some(active.unwrap_or(0)));
+    }
+
+    pub fn next(&mut self, config: &Config) {
+        let index = self.state.selected().unwrap_or(0);
+        self.state.select(Some((index + 1).min(config.profiles.len().saturating_sub(1))));
+    }
+
+    pub fn previous(&mut self) {
+        let index = self.state.selected().unwrap_or(0);
+        self.state.select(Some(index.saturating_sub(1)));
+    }
+
+    pub fn selected<'a>(&self, config: &'a Config) -> Option<&'a Profile> {
+        config.profiles.get(self.state.selected()?)
+    }
+
+    pub fn draw(&mut self, f: &mut Frame<F>, config: &Config) {
+        let area = f.size();
+        let height = (config.profiles.len().max(1) as u16 + 2).min(area.height.saturating_sub(1));
+        let width = MENU_WIDTH.min(area.width);
+        let rect = Rect::new((area.width - width) / 2, (area.height - height) / 2, width, height);
+        if rect.height < 3 {
+            return;
+        }
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::White))
+            .title(Span::styled("Servers", Style::default().fg(Color::White)));
+        let inner = block.inner(rect);
+        f.render_widget(Clear, rect);
+        f.render_widget(block, rect);
+        if config.profiles.is_empty() {
+            let message = Span::styled("No profiles in the config", Style::default().fg(Color::DarkGray));
+            f.render_widget(Paragraph::new(message), inner);
+            return;
+        }
+        let items = config.profiles.iter()
+            .map(|profile| {
+                let active = config.profile.as_ref() == Some(&profile.name);
+                let address = if profile.host.starts_with('/') {
+                    profile.host.clone()
+                } else {
+                    format!("{}:{}", profile.host, profile.port)
+                };
+                ListItem::new(format!("{}{} ({})", if active { "* " } else { "" }, profile.name, address))
+            })
+            .collect::<Vec<ListItem>>();
+        let list = List::new(items)
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            .highlight_symbol("> ");
+        f.render_stateful_widget(list, inner, &mut self.state);
+    }
+}
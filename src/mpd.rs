@@ -0,0 +1,751 @@
+use std::{fmt, io};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum MpdError {
+    Io(io::Error),
+    /// The server sent something that is not valid protocol.
+    Protocol(String),
+    /// An `ACK` line: the server rejected a command.
+    Server {
+        code: u32,
+        command: String,
+        message: String,
+    },
+    /// The server's protocol version is too old for a command.
+    Unsupported {
+        command: &'static str,
+        version: String,
+    },
+}
+
+impl fmt::Display for MpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MpdError::Io(err) => write!(f, "{}", err),
+            MpdError::Protocol(line) => write!(f, "unexpected response: {}", line),
+            MpdError::Server { command, message, .. } if command.is_empty() => write!(f, "{}", message),
+            MpdError::Server { command, message, .. } => write!(f, "{}: {}", command, message),
+            MpdError::Unsupported { command, version } => write!(f, "{} is not supported by MPD {}", command, version),
+        }
+    }
+}
+
+impl From<io::Error> for MpdError {
+    fn from(err: io::Error) -> Self {
+        MpdError::Io(err)
+    }
+}
+
+/// The partition every connection starts in.
+pub const DEFAULT_PARTITION: &str = "default";
+
+/// The `ACK` code for something that does not exist, like a missing sticker.
+const ACK_NO_EXIST: u32 = 50;
+
+/// Largest binary payload accepted, as one chunk or a whole picture. Covers
+/// are far smaller, so a larger length means the server is broken.
+const MAX_BINARY_SIZE: usize = 64 << 20;
+
+/// Every subsystem `idle` can report. Widgets are sent all of them after
+/// connecting so they can load their initial state.
+pub const ALL_SUBSYSTEMS: &[&str] = &[
+    "database", "update", "stored_playlist", "playlist", "player", "mixer",
+    "output", "options", "partition", "sticker", "subscription", "message",
+    "neighbor", "mount",
+];
+
+/// The `key: value` lines of a response, in order, and the binary payload
+/// of commands like `albumart`.
+#[derive(Debug, Default)]
+pub struct Response {
+    pub pairs: Vec<(String, String)>,
+    pub binary: Option<Vec<u8>>,
+}
+
+impl Response {
+    /// The first value for `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn parse<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key).and_then(|value| value.parse().ok())
+    }
+
+    /// Splits a list response into records, each starting at a `key` line.
+    /// Lines before the first `key` are dropped.
+    pub fn records(&self, key: &str) -> Vec<Response> {
+        let mut records: Vec<Response> = Vec::new();
+        for (k, v) in &self.pairs {
+            if k == key {
+                records.push(Response::default());
+            }
+            if let Some(record) = records.last_mut() {
+                record.pairs.push((k.clone(), v.clone()));
+            }
+        }
+        records
+    }
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> io::Result<Stream> {
+        Ok(match self {
+            Stream::Tcp(stream) => Stream::Tcp(stream.try_clone()?),
+            Stream::Unix(stream) => Stream::Unix(stream.try_clone()?),
+        })
+    }
+}
+
+impl Stream {
+    fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+            Stream::Unix(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+        }
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            Stream::Unix(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+/// Closes a connection from another thread, making a blocked call on it
+/// like `idle` return an error.
+pub struct Closer(Stream);
+
+impl Closer {
+    pub fn close(&self) {
+        let _ = self.0.shutdown();
+    }
+
+    /// Makes a waiting `idle` return early with no changes. MPD ignores
+    /// `noidle` when the connection is not idle.
+    pub fn noidle(&mut self) {
+        let _ = self.0.write_all(b"noidle\n");
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A blocking connection to an MPD server.
+pub struct MpdClient {
+    reader: BufReader<Stream>,
+    writer: Stream,
+    /// Protocol version from the server's greeting.
+    version: String,
+    /// How long a command may take before the connection is given up on.
+    timeout: Option<Duration>,
+    /// The partition commands act on.
+    partition: String,
+}
+
+impl MpdClient {
+    /// Connects to `host:port`, or to the Unix socket at `host` if it is a path.
+    /// `timeout` applies to connecting and to every command but `idle`.
+    pub fn connect(host: &str, port: u16, timeout: Option<Duration>) -> Result<Self, MpdError> {
+        let stream = if host.starts_with('/') {
+            Stream::Unix(UnixStream::connect(host)?)
+        } else {
+            Stream::Tcp(connect_tcp(host, port, timeout)?)
+        };
+        stream.set_timeout(timeout)?;
+        let mut client = MpdClient {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+            version: String::new(),
+            timeout,
+            partition: String::from(DEFAULT_PARTITION),
+        };
+        let greeting = client.read_line()?;
+        match greeting.strip_prefix("OK MPD ") {
+            Some(version) => client.version = version.to_string(),
+            None => return Err(MpdError::Protocol(greeting)),
+        }
+        Ok(client)
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn partition(&self) -> &str {
+        &self.partition
+    }
+
+    /// Moves the connection to another partition, so player, queue and
+    /// output commands act on it. Does nothing if it is already there.
+    pub fn enter_partition(&mut self, name: &str) -> Result<(), MpdError> {
+        if name == self.partition {
+            return Ok(());
+        }
+        self.require("partition", 0, 22)?;
+        self.command(&format!("partition {}", quote(name)))?;
+        self.partition = name.to_string();
+        Ok(())
+    }
+
+    /// The names of the server's partitions.
+    pub fn partitions(&mut self) -> Result<Vec<String>, MpdError> {
+        self.require("listpartitions", 0, 22)?;
+        let response = self.command("listpartitions")?;
+        Ok(response.pairs.into_iter()
+            .filter(|(key, _)| key == "partition")
+            .map(|(_, name)| name)
+            .collect())
+    }
+
+    /// Whether the server speaks at least protocol version `major.minor`.
+    pub fn at_least(&self, major: u32, minor: u32) -> bool {
+        let mut parts = self.version.split('.').map(|part| part.parse::<u32>().unwrap_or(0));
+        let version = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+        version >= (major, minor)
+    }
+
+    /// Fails with `Unsupported` if `command` needs a newer server.
+    pub fn require(&self, command: &'static str, major: u32, minor: u32) -> Result<(), MpdError> {
+        if self.at_least(major, minor) {
+            Ok(())
+        } else {
+            Err(MpdError::Unsupported {
+                command,
+                version: self.version.clone(),
+            })
+        }
+    }
+
+    /// Sends the server password, which unlocks commands the server's
+    /// default permissions do not allow.
+    pub fn password(&mut self, password: &str) -> Result<(), MpdError> {
+        self.command(&format!("password {}", quote(password)))?;
+        Ok(())
+    }
+
+    pub fn closer(&self) -> Result<Closer, MpdError> {
+        Ok(Closer(self.writer.try_clone()?))
+    }
+
+    /// Sends one command line and reads its response. Arguments must already
+    /// be quoted with `quote` where needed.
+    pub fn command(&mut self, command: &str) -> Result<Response, MpdError> {
+        self.send(command).and_then(|_| self.read_response()).map_err(timed_out)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), MpdError> {
+        self.writer.write_all(command.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn status(&mut self) -> Result<Response, MpdError> {
+        self.command("status")
+    }
+
+    /// Waits for changes and returns the names of the subsystems that changed.
+    /// There is no timeout while waiting.
+    pub fn idle(&mut self) -> Result<Vec<String>, MpdError> {
+        self.start_idle()?;
+        self.finish_idle()
+    }
+
+    /// Sends `idle` without waiting for the answer, so another thread can
+    /// know it was sent before interrupting it with `Closer::noidle`.
+    pub fn start_idle(&mut self) -> Result<(), MpdError> {
+        self.send("idle")
+    }
+
+    /// Waits for the answer to `start_idle`.
+    pub fn finish_idle(&mut self) -> Result<Vec<String>, MpdError> {
+        self.writer.set_timeout(None)?;
+        let response = self.read_response();
+        self.writer.set_timeout(self.timeout)?;
+        Ok(response?.pairs.into_iter()
+            .filter(|(key, _)| key == "changed")
+            .map(|(_, value)| value)
+            .collect())
+    }
+
+    /// Reads the messages that arrived on subscribed channels since the last
+    /// call, as channel and message.
+    pub fn read_messages(&mut self) -> Result<Vec<(String, String)>, MpdError> {
+        let response = self.command("readmessages")?;
+        Ok(response.records("channel").into_iter()
+            .filter_map(|record| Some((record.get("channel")?.to_string(), record.get("message")?.to_string())))
+            .collect())
+    }
+
+    /// The value of the song sticker `name`, or `None` if the song has none.
+    pub fn sticker(&mut self, uri: &str, name: &str) -> Result<Option<String>, MpdError> {
+        match self.command(&format!("sticker get song {} {}", quote(uri), quote(name))) {
+            Ok(response) => Ok(response.get("sticker")
+                .and_then(|sticker| sticker.split_once('='))
+                .map(|(_, value)| value.to_string())),
+            Err(MpdError::Server { code: ACK_NO_EXIST, .. }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn set_sticker(&mut self, uri: &str, name: &str, value: &str) -> Result<(), MpdError> {
+        self.command(&format!("sticker set song {} {} {}", quote(uri), quote(name), quote(value)))?;
+        Ok(())
+    }
+
+    /// Removes the song sticker `name`. Removing one that is not there is
+    /// not an error.
+    pub fn delete_sticker(&mut self, uri: &str, name: &str) -> Result<(), MpdError> {
+        match self.command(&format!("sticker delete song {} {}", quote(uri), quote(name))) {
+            Ok(_) | Err(MpdError::Server { code: ACK_NO_EXIST, .. }) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Every song that has the sticker `name`, as URI and value.
+    pub fn find_stickers(&mut self, name: &str) -> Result<Vec<(String, String)>, MpdError> {
+        let response = self.command(&format!("sticker find song \"\" {}", quote(name)))?;
+        Ok(response.records("file").into_iter()
+            .filter_map(|record| {
+                let (_, value) = record.get("sticker")?.split_once('=')?;
+                Some((record.get("file")?.to_string(), value.to_string()))
+            })
+            .collect())
+    }
+
+    /// Reads the whole binary payload of `command` (`albumart` or
+    /// `readpicture`) for `uri`, one chunk at a time. Returns `None` if the
+    /// song has no picture.
+    pub fn read_picture(&mut self, command: &str, uri: &str) -> Result<Option<Vec<u8>>, MpdError> {
+        let mut data = Vec::new();
+        loop {
+            let response = match self.command(&format!("{} {} {}", command, quote(uri), data.len())) {
+                Ok(response) => response,
+                Err(MpdError::Server { code: ACK_NO_EXIST, .. }) => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            let size = response.parse::<usize>("size").unwrap_or(0);
+            if size > MAX_BINARY_SIZE {
+                return Err(MpdError::Protocol(format!("size: {}", size)));
+            }
+            match response.binary {
+                Some(chunk) if !chunk.is_empty() => {
+                    if data.len() + chunk.len() > MAX_BINARY_SIZE {
+                        return Err(MpdError::Protocol(format!("{} bytes of binary data", data.len() + chunk.len())));
+                    }
+                    data.extend(chunk);
+                }
+                _ => return Ok(None),
+            }
+            if data.len() >= size {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String, MpdError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(MpdError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")));
+        }
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        Ok(line)
+    }
+
+    fn read_response(&mut self) -> Result<Response, MpdError> {
+        let mut response = Response::default();
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(response);
+            }
+            if let Some(ack) = line.strip_prefix("ACK ") {
+                return Err(parse_ack(ack));
+            }
+            self.read_pair(line, &mut response)?;
+        }
+    }
+
+    /// Adds a `key: value` line to `response`, reading the payload that
+    /// follows a `binary` line.
+    fn read_pair(&mut self, line: String, response: &mut Response) -> Result<(), MpdError> {
+        match line.split_once(": ") {
+            Some(("binary", length)) => {
+                // the payload is followed by a newline
+                let length = length.parse::<usize>().ok()
+                    .filter(|length| *length <= MAX_BINARY_SIZE)
+                    .and_then(|length| length.checked_add(1))
+                    .ok_or_else(|| MpdError::Protocol(line.clone()))?;
+                let mut binary = vec![0; length];
+                self.reader.read_exact(&mut binary)?;
+                binary.pop();
+                response.binary = Some(binary);
+            }
+            Some((key, value)) => response.pairs.push((key.to_string(), value.to_string())),
+            None => return Err(MpdError::Protocol(line)),
+        }
+        Ok(())
+    }
+
+    /// Adds songs to the queue in one batch, in order from `position`, or
+    /// at the end.
+    pub fn add_all<S: AsRef<str>>(&mut self, uris: &[S], position: Option<usize>) -> Result<BatchResult, MpdError> {
+        let mut batch = Batch::new();
+        for (index, uri) in uris.iter().enumerate() {
+            match position {
+                Some(position) => batch.push(format!("addid {} {}", quote(uri.as_ref()), position + index)),
+                None => batch.push(format!("add {}", quote(uri.as_ref()))),
+            };
+        }
+        self.run_batch(&batch)
+    }
+
+    /// Runs `batch` as command lists of at most `BATCH_SIZE` commands each,
+    /// stopping at the first command the server rejects. Only connection and
+    /// protocol errors are returned as errors; a rejected command is reported
+    /// in the result.
+    pub fn run_batch(&mut self, batch: &Batch) -> Result<BatchResult, MpdError> {
+        self.send_batch(batch).map_err(timed_out)
+    }
+
+    fn send_batch(&mut self, batch: &Batch) -> Result<BatchResult, MpdError> {
+        let mut result = BatchResult {
+            responses: Vec::with_capacity(batch.len()),
+            failure: None,
+            total: batch.len(),
+        };
+        for chunk in batch.commands.chunks(BATCH_SIZE) {
+            let mut list = String::from("command_list_ok_begin\n");
+            for command in chunk {
+                list.push_str(command);
+                list.push('\n');
+            }
+            list.push_str("command_list_end");
+            self.send(&list)?;
+            let mut response = Response::default();
+            loop {
+                let line = self.read_line()?;
+                if line == "OK" {
+                    break;
+                }
+                if line == "list_OK" {
+                    result.responses.push(std::mem::take(&mut response));
+                    continue;
+                }
+                if let Some(ack) = line.strip_prefix("ACK ") {
+                    // the server drops the rest of the list
+                    result.failure = Some((result.responses.len(), parse_ack(ack)));
+                    return Ok(result);
+                }
+                self.read_pair(line, &mut response)?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+impl MpdClient {
+    /// A client of version `version` talking to the returned end of a socket
+    /// pair, which plays the server.
+    pub fn pair(version: &str) -> (MpdClient, UnixStream) {
+        let (ours, server) = UnixStream::pair().unwrap();
+        let client = MpdClient {
+            writer: Stream::Unix(ours.try_clone().unwrap()),
+            reader: BufReader::new(Stream::Unix(ours)),
+            version: version.to_string(),
+            timeout: None,
+            partition: String::from(DEFAULT_PARTITION),
+        };
+        (client, server)
+    }
+}
+
+/// Gives read timeouts a readable message.
+fn timed_out(err: MpdError) -> MpdError {
+    match err {
+        // a read timeout shows up as WouldBlock on Unix
+        MpdError::Io(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+            MpdError::Io(io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for MPD"))
+        }
+        err => err,
+    }
+}
+
+/// Most commands sent in one command list. MPD limits the size of a list
+/// (`max_command_list_size`), so long batches are split.
+const BATCH_SIZE: usize = 500;
+
+/// Commands to send together with `MpdClient::run_batch`, saving a round
+/// trip per command. Arguments must already be quoted.
+///
+/// ```ignore
+/// let mut batch = Batch::new();
+/// for uri in uris {
+///     batch.push(format!("add {}", quote(uri)));
+/// }
+/// let result = client.run_batch(&batch)?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Batch {
+    commands: Vec<String>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Batch::default()
+    }
+
+    pub fn push(&mut self, command: impl Into<String>) -> &mut Self {
+        self.commands.push(command.into());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// What happened to each command of a batch.
+#[derive(Debug)]
+pub struct BatchResult {
+    /// Responses of the commands that succeeded, in order.
+    pub responses: Vec<Response>,
+    /// The index of the command that failed and why. The commands after it
+    /// were not run.
+    pub failure: Option<(usize, MpdError)>,
+    /// Commands in the batch.
+    pub total: usize,
+}
+
+impl BatchResult {
+    pub fn is_ok(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// Commands that were not run because an earlier one failed.
+    pub fn skipped(&self) -> usize {
+        self.total - self.responses.len() - self.failure.is_some() as usize
+    }
+
+    /// A message for the bottom bar, e.g. `Added 12 of 40 songs; #13 failed:
+    /// add: No such song; 27 skipped`. `what` names the commands, like
+    /// `songs`; `done` is the past tense verb, like `Added`.
+    pub fn summary(&self, done: &str, what: &str) -> String {
+        match &self.failure {
+            None => format!("{} {} {}", done, self.total, what),
+            Some((index, err)) => format!(
+                "{} {} of {} {}; #{} failed: {}; {} skipped",
+                done,
+                self.responses.len(),
+                self.total,
+                what,
+                index + 1,
+                err,
+                self.skipped(),
+            ),
+        }
+    }
+}
+
+/// Tries each address of `host` in turn, like `TcpStream::connect`, but with
+/// a timeout.
+fn connect_tcp(host: &str, port: u16, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return TcpStream::connect((host, port)),
+    };
+    let mut error = io::Error::new(io::ErrorKind::NotFound, format!("could not resolve {}", host));
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => error = err,
+        }
+    }
+    Err(error)
+}
+
+/// Parses the part of an error line after `ACK `: `[code@index] {command} message`.
+fn parse_ack(ack: &str) -> MpdError {
+    let code = ack.strip_prefix('[')
+        .and_then(|rest| rest.split('@').next())
+        .and_then(|code| code.parse().ok())
+        .unwrap_or(0);
+    let command = ack.split_once('{')
+        .and_then(|(_, rest)| rest.split_once('}'))
+        .map(|(command, _)| command.to_string())
+        .unwrap_or_default();
+    let message = ack.split_once("} ")
+        .map(|(_, message)| message.to_string())
+        .unwrap_or_else(|| ack.to_string());
+    MpdError::Server {
+        code,
+        command,
+        message,
+    }
+}
+
+/// Quotes a command argument, escaping backslashes and double quotes.
+pub fn quote(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_by_number() {
+        let (old, _server) = MpdClient::pair("0.21.25");
+        assert!(old.at_least(0, 21));
+        assert!(!old.at_least(0, 22));
+        let (new, _server) = MpdClient::pair("0.22.0");
+        assert!(new.at_least(0, 21));
+        assert!(new.at_least(0, 22));
+        assert!(!new.at_least(0, 23));
+        let (next, _server) = MpdClient::pair("1.0");
+        assert!(next.at_least(0, 22));
+    }
+
+    #[test]
+    fn require_names_the_command_and_version() {
+        let (client, _server) = MpdClient::pair("0.21.3");
+        assert!(client.require("albumart", 0, 21).is_ok());
+        match client.require("readpicture", 0, 22) {
+            Err(MpdError::Unsupported { command, version }) => {
+                assert_eq!(command, "readpicture");
+                assert_eq!(version, "0.21.3");
+            }
+            other => panic!("expected Unsupported, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn batch(count: usize) -> Batch {
+        let mut batch = Batch::new();
+        for index in 0..count {
+            batch.push(format!("add \"song{}\"", index));
+        }
+        batch
+    }
+
+    #[test]
+    fn batch_collects_each_response() {
+        let (mut client, mut server) = MpdClient::pair("0.23.5");
+        write!(server, "Id: 10\nlist_OK\nId: 11\nlist_OK\nOK\n").unwrap();
+        let result = client.run_batch(&batch(2)).unwrap();
+        assert!(result.is_ok());
+        let ids = result.responses.iter().map(|response| response.get("Id").unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, ["10", "11"]);
+        assert_eq!(result.summary("Added", "songs"), "Added 2 songs");
+    }
+
+    #[test]
+    fn batch_stops_at_a_failure_mid_list() {
+        let (mut client, mut server) = MpdClient::pair("0.23.5");
+        write!(server, "list_OK\nlist_OK\nACK [50@2] {{add}} No such song\n").unwrap();
+        let result = client.run_batch(&batch(5)).unwrap();
+        assert_eq!(result.responses.len(), 2);
+        assert!(matches!(&result.failure, Some((2, MpdError::Server { code: ACK_NO_EXIST, .. }))));
+        assert_eq!(result.skipped(), 2);
+        assert_eq!(result.summary("Added", "songs"), "Added 2 of 5 songs; #3 failed: add: No such song; 2 skipped");
+    }
+
+    #[test]
+    fn batch_failure_in_a_later_list_counts_from_the_start() {
+        let (mut client, mut server) = MpdClient::pair("0.23.5");
+        let first = "list_OK\n".repeat(BATCH_SIZE);
+        write!(server, "{}OK\nlist_OK\nACK [50@1] {{add}} No such song\n", first).unwrap();
+        let result = client.run_batch(&batch(BATCH_SIZE + 3)).unwrap();
+        assert_eq!(result.responses.len(), BATCH_SIZE + 1);
+        assert!(matches!(&result.failure, Some((index, _)) if *index == BATCH_SIZE + 1));
+        assert_eq!(result.skipped(), 1);
+        // both lists were sent, the second only after the first was answered
+        drop(client);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).unwrap();
+        assert_eq!(sent.matches("command_list_ok_begin").count(), 2);
+        assert!(sent.ends_with(&format!("add \"song{}\"\ncommand_list_end\n", BATCH_SIZE + 2)));
+    }
+
+    #[test]
+    fn pictures_are_read_in_chunks() {
+        let (mut client, mut server) = MpdClient::pair("0.23.5");
+        server.write_all(b"size: 5\nbinary: 3\nabc\nOK\nsize: 5\nbinary: 2\nde\nOK\n").unwrap();
+        assert_eq!(client.read_picture("albumart", "a.flac").unwrap(), Some(b"abcde".to_vec()));
+    }
+
+    #[test]
+    fn oversized_binary_lengths_are_rejected() {
+        for response in [
+            format!("size: 10\nbinary: {}\n", usize::MAX),
+            format!("size: 10\nbinary: {}\n", MAX_BINARY_SIZE + 1),
+            format!("size: {}\nbinary: 1\na\nOK\n", MAX_BINARY_SIZE + 1),
+        ] {
+            let (mut client, mut server) = MpdClient::pair("0.23.5");
+            server.write_all(response.as_bytes()).unwrap();
+            assert!(matches!(client.read_picture("albumart", "a.flac"), Err(MpdError::Protocol(_))), "{:?}", response);
+        }
+    }
+
+    #[test]
+    fn batch_reports_a_bad_line_as_an_error() {
+        let (mut client, mut server) = MpdClient::pair("0.23.5");
+        write!(server, "list_OK\nnonsense\n").unwrap();
+        assert!(matches!(client.run_batch(&batch(2)), Err(MpdError::Protocol(_))));
+    }
+}
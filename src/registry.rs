@@ -0,0 +1,269 @@
+use serde_json::Value;
+use crate::containers::{BasicContainer, BasicWidget, Container, HSplitContainer, VSplitContainer, Widget};
+use crate::layout::{self, LayoutError};
+use crate::widgets::album_art::AlbumArtWidget;
+use crate::widgets::external::ExternalWidget;
+use crate::widgets::lyrics::LyricsWidget;
+use crate::widgets::messages::MessagesWidget;
+use crate::widgets::outputs::OutputsWidget;
+use crate::widgets::partitions::PartitionsWidget;
+use crate::widgets::queue::QueueWidget;
+use crate::widgets::script::ScriptWidget;
+use crate::widgets::song_info::SongInfoWidget;
+use crate::widgets::stats::StatsWidget;
+use crate::widgets::terminal::TerminalWidget;
+use crate::widgets::visualizer::VisualizerWidget;
+
+pub type ContainerLoader = fn(&Value, &Registry) -> Result<Box<dyn Container>, LayoutError>;
+pub type WidgetLoader = fn(&Value, &Registry) -> Result<Box<dyn Widget>, LayoutError>;
+/// Checks a layout value the way the loader reads it, without building
+/// anything, so no widget starts a command, terminal, script or thread.
+pub type ContainerChecker = fn(&Value, &Registry) -> Result<(), LayoutError>;
+pub type WidgetChecker = fn(&Value) -> Result<(), LayoutError>;
+
+pub struct ContainerKind {
+    /// The `type` written to the layout file.
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub create: fn() -> Box<dyn Container>,
+    pub load: ContainerLoader,
+    pub check: ContainerChecker,
+}
+
+pub struct WidgetKind {
+    /// The `type` written to the layout file.
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub create: fn() -> Box<dyn Widget>,
+    pub load: WidgetLoader,
+    pub check: WidgetChecker,
+}
+
+/// One row of the Insert mode menu.
+pub enum Entry<'a> {
+    Container(&'a ContainerKind),
+    Widget(&'a WidgetKind),
+}
+
+impl Entry<'_> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Entry::Container(kind) => kind.name,
+            Entry::Widget(kind) => kind.name,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Entry::Container(kind) => kind.description,
+            Entry::Widget(kind) => kind.description,
+        }
+    }
+
+    /// A new default instance, with widgets wrapped in a basic container.
+    pub fn create(&self) -> Box<dyn Container> {
+        match self {
+            Entry::Container(kind) => (kind.create)(),
+            Entry::Widget(kind) => Box::new(BasicContainer::new((kind.create)())),
+        }
+    }
+}
+
+/// Every container and widget that can be inserted from the UI or loaded from
+/// a layout file. Root and basic containers are structural and handled by the
+/// layout loader directly.
+pub struct Registry {
+    containers: Vec<ContainerKind>,
+    widgets: Vec<WidgetKind>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry {
+            containers: Vec::new(),
+            widgets: Vec::new(),
+        };
+        registry.register_container(ContainerKind {
+            id: "hsplit",
+            name: "Horizontal Split Container",
+            description: "Two panes side by side",
+            create: || Box::new(HSplitContainer::default()),
+            load: |value, registry| Ok(Box::new(HSplitContainer::from_value(value, registry)?)),
+            check: layout::check_split,
+        });
+        registry.register_container(ContainerKind {
+            id: "vsplit",
+            name: "Vertical Split Container",
+            description: "Two panes stacked on top of each other",
+            create: || Box::new(VSplitContainer::default()),
+            load: |value, registry| Ok(Box::new(VSplitContainer::from_value(value, registry)?)),
+            check: layout::check_split,
+        });
+        registry.register_widget(WidgetKind {
+            id: "basic",
+            name: "Basic Widget",
+            description: "A title and a block of text",
+            create: || Box::new(BasicWidget::default()),
+            load: |value, registry| Ok(Box::new(BasicWidget::from_value(value)?)),
+            check: layout::check_pane,
+        });
+        registry.register_widget(WidgetKind {
+            id: "visualizer",
+            name: "Visualizer",
+            description: "Spectrum or waveform read from MPD's fifo output",
+            create: || Box::new(VisualizerWidget::default()),
+            load: |value, registry| Ok(Box::new(VisualizerWidget::from_value(value)?)),
+            check: VisualizerWidget::check,
+        });
+        registry.register_widget(WidgetKind {
+            id: "album_art",
+            name: "Album Art",
+            description: "Cover of the current song, drawn with half blocks or braille",
+            create: || Box::new(AlbumArtWidget::default()),
+            load: |value, registry| Ok(Box::new(AlbumArtWidget::from_value(value)?)),
+            check: AlbumArtWidget::check,
+        });
+        registry.register_widget(WidgetKind {
+            id: "lyrics",
+            name: "Lyrics",
+            description: "Lyrics from a local .lrc or .txt file, synced to playback",
+            create: || Box::new(LyricsWidget::default()),
+            load: |value, registry| Ok(Box::new(LyricsWidget::from_value(value)?)),
+            check: layout::check_pane,
+        });
+        registry.register_widget(WidgetKind {
+            id: "song_info",
+            name: "Song Info",
+            description: "Every tag, comment and format detail of the current song",
+            create: || Box::new(SongInfoWidget::default()),
+            load: |value, registry| Ok(Box::new(SongInfoWidget::from_value(value)?)),
+            check: layout::check_pane,
+        });
+        registry.register_widget(WidgetKind {
+            id: "outputs",
+            name: "Outputs",
+            description: "Audio outputs, volume, replay gain, crossfade and MixRamp",
+            create: || Box::new(OutputsWidget::default()),
+            load: |value, registry| Ok(Box::new(OutputsWidget::from_value(value)?)),
+            check: layout::check_pane,
+        });
+        registry.register_widget(WidgetKind {
+            id: "stats",
+            name: "Stats",
+            description: "Library counts, uptime, database updates and playback format",
+            create: || Box::new(StatsWidget::default()),
+            load: |value, registry| Ok(Box::new(StatsWidget::from_value(value)?)),
+            check: layout::check_pane,
+        });
+        registry.register_widget(WidgetKind {
+            id: "queue",
+            name: "Queue",
+            description: "The play queue with song ratings",
+            create: || Box::new(QueueWidget::default()),
+            load: |value, registry| Ok(Box::new(QueueWidget::from_value(value)?)),
+            check: layout::check_pane,
+        });
+        registry.register_widget(WidgetKind {
+            id: "messages",
+            name: "Messages",
+            description: "Subscribe to MPD channels, read and send client-to-client messages",
+            create: || Box::new(MessagesWidget::default()),
+            load: |value, registry| Ok(Box::new(MessagesWidget::from_value(value)?)),
+            check: MessagesWidget::check,
+        });
+        registry.register_widget(WidgetKind {
+            id: "partitions",
+            name: "Partitions",
+            description: "List partitions, pick the one a pane controls and move outputs between them",
+            create: || Box::new(PartitionsWidget::default()),
+            load: |value, registry| Ok(Box::new(PartitionsWidget::from_value(value)?)),
+            check: layout::check_pane,
+        });
+        registry.register_widget(WidgetKind {
+            id: "script",
+            name: "Script",
+            description: "A widget drawn by a Rhai script from the scripts directory",
+            create: || Box::new(ScriptWidget::default()),
+            load: |value, registry| Ok(Box::new(ScriptWidget::from_value(value)?)),
+            check: ScriptWidget::check,
+        });
+        registry.register_widget(WidgetKind {
+            id: "external",
+            name: "External",
+            description: "The output of a shell command, run once or on an interval",
+            create: || Box::new(ExternalWidget::default()),
+            load: |value, registry| Ok(Box::new(ExternalWidget::from_value(value)?)),
+            check: ExternalWidget::check,
+        });
+        registry.register_widget(WidgetKind {
+            id: "terminal",
+            name: "Terminal",
+            description: "A shell in a terminal, or another command given as the text",
+            create: || Box::new(TerminalWidget::default()),
+            load: |value, registry| Ok(Box::new(TerminalWidget::from_value(value)?)),
+            check: TerminalWidget::check,
+        });
+        registry
+    }
+}
+
+impl Registry {
+    pub fn register_container(&mut self, kind: ContainerKind) {
+        self.containers.push(kind);
+    }
+
+    pub fn register_widget(&mut self, kind: WidgetKind) {
+        self.widgets.push(kind);
+    }
+
+    pub fn container(&self, id: &str) -> Option<&ContainerKind> {
+        self.containers.iter().find(|kind| kind.id == id)
+    }
+
+    pub fn widget(&self, id: &str) -> Option<&WidgetKind> {
+        self.widgets.iter().find(|kind| kind.id == id)
+    }
+
+    /// Containers followed by widgets whose name or description contains
+    /// `filter`, ignoring case.
+    pub fn entries(&self, filter: &str) -> Vec<Entry<'_>> {
+        let filter = filter.to_lowercase();
+        self.containers.iter().map(Entry::Container)
+            .chain(self.widgets.iter().map(Entry::Widget))
+            .filter(|entry| {
+                entry.name().to_lowercase().contains(&filter)
+                    || entry.description().to_lowercase().contains(&filter)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn checking_builds_nothing() {
+        let mut registry = Registry::default();
+        for kind in &mut registry.containers {
+            kind.create = || unreachable!("a container was created");
+            kind.load = |_, _| unreachable!("a container was loaded");
+        }
+        for kind in &mut registry.widgets {
+            kind.create = || unreachable!("a widget was created");
+            kind.load = |_, _| unreachable!("a widget was loaded");
+        }
+        // every kind of widget, in nested splits of both kinds
+        let mut leaves = registry.widgets.iter()
+            .map(|kind| json!({"type": "basic", "widget": {"type": kind.id, "title": kind.name}}));
+        let first = leaves.next().unwrap();
+        let tree = leaves.zip(["hsplit", "vsplit"].into_iter().cycle()).fold(first, |tree, (leaf, kind)| {
+            json!({"type": kind, "children": [tree, leaf]})
+        });
+        let layout = json!({"type": "root", "child": tree});
+        assert!(layout::check_container(&layout, &registry).is_ok());
+    }
+}
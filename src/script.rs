@@ -0,0 +1,376 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, AST};
+use serde_json::Value;
+use termion::event::Key;
+use crate::ThingEvent;
+use crate::config;
+use crate::control::{Command, Request};
+use crate::mpd::{MpdClient, MpdError, Response};
+
+/// Operations a script may run per call before it is stopped, so a loop
+/// that never ends does not hang the app.
+const MAX_OPERATIONS: u64 = 1_000_000;
+/// Largest strings, arrays and maps a script may build, so one cannot use up
+/// the app's memory. Arrays are large enough for the song list of a big
+/// library.
+const MAX_STRING_SIZE: usize = 1 << 20;
+const MAX_ARRAY_SIZE: usize = 500_000;
+const MAX_MAP_SIZE: usize = 10_000;
+/// How deep script functions may call each other.
+const MAX_CALL_LEVELS: usize = 32;
+
+/// MPD commands scripts may not send. They would end or change the state of
+/// the connection scripts share with the app: waiting for events, logging in
+/// with other permissions, moving to another partition, changing what
+/// responses contain or starting a command list.
+const FORBIDDEN_COMMANDS: &[&str] = &[
+    "idle",
+    "noidle",
+    "close",
+    "kill",
+    "password",
+    "partition",
+    "tagtypes",
+    "protocol",
+    "binarylimit",
+    "command_list_begin",
+    "command_list_ok_begin",
+    "command_list_end",
+];
+
+/// Where scripts are kept, as `<name>.rhai`.
+pub fn scripts_dir() -> PathBuf {
+    config::config_dir().join("scripts")
+}
+
+/// Where `app` sends commands, set once the main loop is listening.
+static EVENTS: OnceLock<Mutex<mpsc::Sender<ThingEvent>>> = OnceLock::new();
+
+pub fn set_events(events: mpsc::Sender<ThingEvent>) {
+    let _ = EVENTS.set(Mutex::new(events));
+}
+
+/// An MPD command from a script, and where its response goes.
+type MpdCall = (String, mpsc::Sender<Result<Response, MpdError>>);
+
+/// A compiled Rhai script and the state it keeps between calls, which its
+/// functions see as `this`.
+///
+/// Scripts can only reach the outside through the functions registered
+/// here: `mpd(command)` returns the first value of every key in MPD's
+/// response, `mpd_list(command, key)` splits it into records starting at
+/// `key`, and `app(command)` runs a control socket command such as
+/// `focus Queue` once the script returns. `import` is not available, and MPD
+/// commands that would disturb the shared connection are refused.
+pub struct Script {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    state: RefCell<Dynamic>,
+    /// The way to the caller's MPD connection during a call.
+    mpd: Arc<Mutex<Option<mpsc::Sender<MpdCall>>>>,
+    /// What the script last printed.
+    printed: Arc<Mutex<Option<String>>>,
+}
+
+impl Script {
+    /// Compiles `scripts/<name>.rhai` and calls its `init` function.
+    pub fn load(name: &str) -> Result<Self, String> {
+        let path = scripts_dir().join(format!("{}.rhai", name));
+        let text = fs::read_to_string(&path).map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        let mpd = Arc::new(Mutex::new(None));
+        let printed = Arc::new(Mutex::new(None));
+        let engine = engine(&mpd, &printed);
+        let ast = engine.compile(&text).map_err(|err| format!("{}: {}", name, err))?;
+        let script = Script {
+            name: name.to_string(),
+            engine,
+            ast,
+            state: RefCell::new(Dynamic::from_map(Map::new())),
+            mpd,
+            printed,
+        };
+        let _ = script.call("init", Vec::new(), None)?;
+        Ok(script)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn has_function(&self, function: &str, params: usize) -> bool {
+        self.ast.iter_functions().any(|f| f.name == function && f.params.len() == params)
+    }
+
+    /// Takes what the script printed since the last call to this.
+    pub fn take_printed(&self) -> Option<String> {
+        self.printed.lock().unwrap().take()
+    }
+
+    /// Calls `function` with `this` bound to the script's state. Functions
+    /// the script does not define return `()`. With a client, the script
+    /// runs on another thread while this one answers its MPD commands.
+    pub fn call(&self, function: &str, args: Vec<Dynamic>, mpd: Option<&mut MpdClient>) -> Result<Dynamic, String> {
+        if !self.has_function(function, args.len()) {
+            return Ok(Dynamic::UNIT);
+        }
+        let mut state = self.state.borrow_mut();
+        let state: &mut Dynamic = &mut state;
+        let (engine, ast) = (&self.engine, &self.ast);
+        let client = match mpd {
+            Some(client) => client,
+            None => return run(engine, ast, function, args, state),
+        };
+        thread::scope(|scope| {
+            let (calls_tx, calls) = mpsc::channel();
+            let lent = Lent::new(&self.mpd, calls_tx);
+            let script = scope.spawn(move || {
+                let _lent = lent;
+                run(engine, ast, function, args, state)
+            });
+            // ends once the script returns and gives the sender back
+            for (command, reply) in calls {
+                let _ = reply.send(client.command(&command));
+            }
+            script.join().unwrap_or_else(|_| Err(format!("{}: the script panicked", function)))
+        })
+        .map_err(|err| format!("{}: {}", self.name, err))
+    }
+}
+
+/// Hands the way to the MPD connection to a script for one call, taking it
+/// back when dropped, even if the script panics.
+struct Lent<'a>(&'a Mutex<Option<mpsc::Sender<MpdCall>>>);
+
+impl<'a> Lent<'a> {
+    fn new(slot: &'a Mutex<Option<mpsc::Sender<MpdCall>>>, calls: mpsc::Sender<MpdCall>) -> Self {
+        *slot.lock().unwrap() = Some(calls);
+        Lent(slot)
+    }
+}
+
+impl Drop for Lent<'_> {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().take();
+    }
+}
+
+fn run(engine: &Engine, ast: &AST, function: &str, args: Vec<Dynamic>, state: &mut Dynamic) -> Result<Dynamic, String> {
+    let options = rhai::CallFnOptions::new().eval_ast(false).bind_this_ptr(state);
+    engine.call_fn_with_options(options, &mut rhai::Scope::new(), ast, function, args)
+        .map_err(|err| err.to_string())
+}
+
+/// An engine with the app's functions and without access to files.
+fn engine(mpd: &Arc<Mutex<Option<mpsc::Sender<MpdCall>>>>, printed: &Arc<Mutex<Option<String>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_MAP_SIZE);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    let printed_by = printed.clone();
+    engine.on_print(move |text| *printed_by.lock().unwrap() = Some(text.to_string()));
+    engine.on_debug(|_, _, _| {});
+    let slot = mpd.clone();
+    engine.register_fn("mpd", move |command: &str| -> Result<Map, Box<EvalAltResult>> {
+        let response = command_for_script(&slot, command)?;
+        let mut map = Map::new();
+        for (key, value) in response.pairs {
+            map.entry(key.into()).or_insert_with(|| value.into());
+        }
+        Ok(map)
+    });
+    let slot = mpd.clone();
+    engine.register_fn("mpd_list", move |command: &str, key: &str| -> Result<Array, Box<EvalAltResult>> {
+        let response = command_for_script(&slot, command)?;
+        Ok(response.records(key).into_iter()
+            .map(|record| {
+                let map = record.pairs.into_iter()
+                    .map(|(key, value)| (key.into(), value.into()))
+                    .collect::<Map>();
+                Dynamic::from_map(map)
+            })
+            .collect())
+    });
+    engine.register_fn("app", |command: &str| -> Result<(), Box<EvalAltResult>> {
+        let command = Command::parse(command)?;
+        if command == Command::State {
+            return Err("state is passed to actions, not read through app".into());
+        }
+        let events = EVENTS.get().ok_or("the app is not running")?;
+        events.lock().unwrap()
+            .send(ThingEvent::Control(Request::detached(command)))
+            .map_err(|_| "the app is not running".into())
+    });
+    engine
+}
+
+/// Sends `command` to the connection lent for the current call.
+fn command_for_script(slot: &Mutex<Option<mpsc::Sender<MpdCall>>>, command: &str) -> Result<Response, Box<EvalAltResult>> {
+    // a line break would send a second command past the check
+    if command.contains(['\n', '\r']) {
+        return Err("MPD commands must be on one line".into());
+    }
+    let name = command.split_whitespace().next().unwrap_or("").to_ascii_lowercase();
+    if FORBIDDEN_COMMANDS.contains(&name.as_str()) {
+        return Err(format!("{} is not available to scripts", name).into());
+    }
+    let calls = slot.lock().unwrap().clone().ok_or("MPD is not available here")?;
+    let (reply_tx, reply) = mpsc::channel();
+    calls.send((command.to_string(), reply_tx)).map_err(|_| "MPD is not available here")?;
+    match reply.recv() {
+        Ok(result) => result.map_err(|err| err.to_string().into()),
+        Err(_) => Err("MPD is not available here".into()),
+    }
+}
+
+/// Converts layout or state JSON for a script.
+pub fn to_dynamic(value: &Value) -> Dynamic {
+    rhai::serde::to_dynamic(value).unwrap_or(Dynamic::UNIT)
+}
+
+/// A key as scripts name it: the character itself, `Enter`, `Up`, `C-a`,
+/// `A-x`, `F5` and so on.
+pub fn key_name(key: Key) -> Option<String> {
+    Some(match key {
+        Key::Char('\n') => String::from("Enter"),
+        Key::Char('\t') => String::from("Tab"),
+        Key::Char(' ') => String::from("Space"),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("C-{}", c),
+        Key::Alt(c) => format!("A-{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Backspace => String::from("Backspace"),
+        Key::Left => String::from("Left"),
+        Key::Right => String::from("Right"),
+        Key::Up => String::from("Up"),
+        Key::Down => String::from("Down"),
+        Key::Home => String::from("Home"),
+        Key::End => String::from("End"),
+        Key::PageUp => String::from("PageUp"),
+        Key::PageDown => String::from("PageDown"),
+        Key::BackTab => String::from("BackTab"),
+        Key::Delete => String::from("Delete"),
+        Key::Insert => String::from("Insert"),
+        Key::Esc => String::from("Esc"),
+        _ => return None,
+    })
+}
+
+/// Keys bound to script functions. Every script in the scripts directory
+/// with a `keys` function is loaded at startup; `keys` returns a map from
+/// key names to the names of functions taking the app's state, the same
+/// JSON the control socket's `state` command returns.
+#[derive(Default)]
+pub struct KeyBindings {
+    scripts: Vec<Script>,
+    /// Key name, index into `scripts` and function name.
+    bindings: Vec<(String, usize, String)>,
+}
+
+impl KeyBindings {
+    /// Loads the bindings, returning the errors of scripts that failed.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut bindings = KeyBindings::default();
+        let mut errors = Vec::new();
+        let mut names = match fs::read_dir(scripts_dir()) {
+            Ok(entries) => entries.flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "rhai"))
+                .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+                .collect::<Vec<String>>(),
+            Err(_) => Vec::new(),
+        };
+        names.sort();
+        for name in names {
+            match bindings.add(&name) {
+                Ok(()) => {}
+                Err(err) => errors.push(err),
+            }
+        }
+        (bindings, errors)
+    }
+
+    fn add(&mut self, name: &str) -> Result<(), String> {
+        let script = Script::load(name)?;
+        if !script.has_function("keys", 0) {
+            return Ok(());
+        }
+        let keys = script.call("keys", Vec::new(), None)?
+            .try_cast::<Map>()
+            .ok_or_else(|| format!("{}: keys must return a map", name))?;
+        let index = self.scripts.len();
+        for (key, function) in keys {
+            let function = function.into_string().map_err(|_| format!("{}: key {} must name a function", name, key))?;
+            if !script.has_function(&function, 1) {
+                return Err(format!("{}: no function {}(state) for key {}", name, function, key));
+            }
+            self.bindings.push((key.to_string(), index, function));
+        }
+        self.scripts.push(script);
+        Ok(())
+    }
+
+    /// Each bound key name, with the name of its script and function.
+    pub fn bound(&self) -> impl Iterator<Item = (&str, &str, &str)> {
+        self.bindings.iter()
+            .map(|(key, script, function)| (key.as_str(), self.scripts[*script].name(), function.as_str()))
+    }
+
+    /// The script and function bound to `key`, if any.
+    pub fn find(&self, key: Key) -> Option<(&Script, &str)> {
+        let name = key_name(key)?;
+        self.bindings.iter()
+            .find(|(bound, _, _)| *bound == name)
+            .map(|(_, script, function)| (&self.scripts[*script], function.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_engine() -> Engine {
+        engine(&Arc::new(Mutex::new(None)), &Arc::new(Mutex::new(None)))
+    }
+
+    fn too_large(result: Result<Dynamic, Box<EvalAltResult>>) -> bool {
+        matches!(result.map_err(|err| *err), Err(EvalAltResult::ErrorDataTooLarge(..)))
+    }
+
+    #[test]
+    fn growing_data_is_stopped() {
+        let engine = test_engine();
+        assert!(too_large(engine.eval("let s = \"x\"; loop { s += s; }")));
+        assert!(too_large(engine.eval("let a = [1]; loop { a += a; }")));
+        // maps are measured when a function returns one, and growing one
+        // by index alone is held back by the operation limit
+        assert!(too_large(engine.eval("let m = #{}; for i in 0..20000 { m[`k${i}`] = i; m.mixin(#{}); } m")));
+    }
+
+    #[test]
+    fn deep_recursion_is_stopped() {
+        let result = test_engine().eval::<Dynamic>("fn down(n) { down(n + 1) } down(0)");
+        assert!(matches!(result.map_err(|err| *err), Err(EvalAltResult::ErrorStackOverflow(..))));
+        assert_eq!(test_engine().eval::<i64>("fn down(n) { if n == 0 { 0 } else { down(n - 1) } } down(20)").unwrap(), 0);
+    }
+
+    #[test]
+    fn connection_commands_are_refused() {
+        let slot = Mutex::new(None);
+        for command in ["idle", "noidle", "close", "password secret", "PARTITION other", "command_list_begin", "kill"] {
+            let err = command_for_script(&slot, command).unwrap_err().to_string();
+            assert!(err.contains("not available to scripts"), "{}: {}", command, err);
+        }
+        let err = command_for_script(&slot, "status\nkill").unwrap_err().to_string();
+        assert!(err.contains("one line"), "{}", err);
+        // allowed commands get as far as the missing connection
+        let err = command_for_script(&slot, "status").unwrap_err().to_string();
+        assert!(err.contains("MPD is not available here"), "{}", err);
+    }
+}
@@ -0,0 +1,185 @@
+use std::time::Instant;
+use crate::mpd::{MpdClient, MpdError, Response};
+
+/// Sticker holding a song's rating, from 1 to `MAX_RATING`.
+pub const RATING: &str = "rating";
+/// Sticker counting how often a song was played to the end.
+pub const PLAY_COUNT: &str = "playcount";
+pub const MAX_RATING: u8 = 5;
+
+/// A song counts as played if it got this close to its end, in seconds.
+/// Status is only read on player events, so the position is estimated.
+const END_MARGIN: f64 = 3.0;
+/// A song that starts over counts as played again only if its position is
+/// this close to its start, in seconds.
+const START_MARGIN: f64 = 2.0;
+
+/// Parses a rating sticker, ignoring values outside the scale.
+pub fn parse_rating(value: &str) -> Option<u8> {
+    value.parse::<u8>().ok().filter(|rating| (1..=MAX_RATING).contains(rating))
+}
+
+/// A rating as stars, padded to the width of the scale.
+pub fn stars(rating: u8) -> String {
+    let rating = rating.min(MAX_RATING) as usize;
+    format!("{}{}", "★".repeat(rating), "☆".repeat(MAX_RATING as usize - rating))
+}
+
+/// Sets a song's rating, or removes it for 0.
+pub fn rate(mpd: &mut MpdClient, uri: &str, rating: u8) -> Result<(), MpdError> {
+    if rating == 0 {
+        mpd.delete_sticker(uri, RATING)
+    } else {
+        mpd.set_sticker(uri, RATING, &rating.min(MAX_RATING).to_string())
+    }
+}
+
+/// Adds one to a song's play count and returns the new count.
+pub fn count_play(mpd: &mut MpdClient, uri: &str) -> Result<u64, MpdError> {
+    let count = mpd.sticker(uri, PLAY_COUNT)?
+        .and_then(|count| count.parse::<u64>().ok())
+        .unwrap_or(0) + 1;
+    mpd.set_sticker(uri, PLAY_COUNT, &count.to_string())?;
+    Ok(count)
+}
+
+/// Follows playback through player events to tell when a song finished.
+/// A song finished if playback moved on, to another song or back to the
+/// start of the same one, after getting near its end.
+#[derive(Default)]
+pub struct PlayTracker {
+    /// Id and URI of the song last seen.
+    song: Option<(String, String)>,
+    duration: Option<f64>,
+    /// Position when the status was read, and when that was.
+    elapsed: f64,
+    since: Option<Instant>,
+    playing: bool,
+}
+
+impl PlayTracker {
+    /// Reads the new status after a player event. Returns the URI of the
+    /// song that finished, if one did.
+    pub fn update(&mut self, mpd: &mut MpdClient) -> Result<Option<String>, MpdError> {
+        let status = mpd.status()?;
+        let uri = match status.get("songid") {
+            Some(id) if !self.is_last(id) => mpd.command("currentsong")?.get("file").map(String::from),
+            _ => None,
+        };
+        Ok(self.observe(&status, uri, Instant::now()))
+    }
+
+    /// Takes in a status read at `now`, with the URI of the current song if
+    /// it is not the one last seen.
+    fn observe(&mut self, status: &Response, uri: Option<String>, now: Instant) -> Option<String> {
+        let elapsed = status.parse::<f64>("elapsed").unwrap_or(0.0);
+        let id = status.get("songid");
+        let same_song = id.is_some_and(|id| self.is_last(id));
+        // seeking back within a song is not a restart, so the same song
+        // must start over from the top
+        let finished = match &self.song {
+            Some((_, uri)) if self.near_end(now) && (!same_song || elapsed < START_MARGIN) => Some(uri.clone()),
+            _ => None,
+        };
+        if !same_song {
+            self.song = id.zip(uri).map(|(id, uri)| (id.to_string(), uri));
+        }
+        self.duration = status.parse::<f64>("duration");
+        self.elapsed = elapsed;
+        self.since = Some(now);
+        self.playing = status.get("state") == Some("play");
+        finished
+    }
+
+    fn is_last(&self, id: &str) -> bool {
+        matches!(&self.song, Some((last_id, _)) if last_id == id)
+    }
+
+    /// The estimated position in the last song at `now`.
+    fn played(&self, now: Instant) -> f64 {
+        match (self.playing, self.since) {
+            (true, Some(since)) => self.elapsed + now.saturating_duration_since(since).as_secs_f64(),
+            _ => self.elapsed,
+        }
+    }
+
+    fn near_end(&self, now: Instant) -> bool {
+        self.duration.is_some_and(|duration| self.played(now) >= duration - END_MARGIN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    fn status(state: &str, id: Option<&str>, elapsed: f64, duration: f64) -> Response {
+        let mut pairs = vec![(String::from("state"), state.to_string())];
+        if let Some(id) = id {
+            pairs.push((String::from("songid"), id.to_string()));
+            pairs.push((String::from("elapsed"), elapsed.to_string()));
+            pairs.push((String::from("duration"), duration.to_string()));
+        }
+        Response { pairs, binary: None }
+    }
+
+    fn after(start: Instant, seconds: f64) -> Instant {
+        start + Duration::from_secs_f64(seconds)
+    }
+
+    /// A tracker that saw song 1, `a.flac`, start playing at `start`.
+    fn playing(start: Instant) -> PlayTracker {
+        let mut tracker = PlayTracker::default();
+        let uri = Some(String::from("a.flac"));
+        assert_eq!(tracker.observe(&status("play", Some("1"), 0.0, 200.0), uri, start), None);
+        tracker
+    }
+
+    #[test]
+    fn playing_to_the_next_song_counts() {
+        let start = Instant::now();
+        let mut tracker = playing(start);
+        let next = tracker.observe(&status("play", Some("2"), 0.1, 180.0), Some(String::from("b.flac")), after(start, 200.0));
+        assert_eq!(next.as_deref(), Some("a.flac"));
+        let stopped = tracker.observe(&status("stop", None, 0.0, 0.0), None, after(start, 380.0));
+        assert_eq!(stopped.as_deref(), Some("b.flac"));
+    }
+
+    #[test]
+    fn skipping_does_not_count() {
+        let start = Instant::now();
+        let mut tracker = playing(start);
+        let next = tracker.observe(&status("play", Some("2"), 0.0, 180.0), Some(String::from("b.flac")), after(start, 60.0));
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn repeating_a_single_song_counts_each_time() {
+        let start = Instant::now();
+        let mut tracker = playing(start);
+        for round in 1..=2 {
+            let again = tracker.observe(&status("play", Some("1"), 0.2, 200.0), None, after(start, 200.0 * round as f64));
+            assert_eq!(again.as_deref(), Some("a.flac"));
+        }
+    }
+
+    #[test]
+    fn seeking_back_near_the_end_does_not_count() {
+        let start = Instant::now();
+        let mut tracker = playing(start);
+        let seek = tracker.observe(&status("play", Some("1"), 150.0, 200.0), None, after(start, 198.5));
+        assert_eq!(seek, None);
+        // the song still counts once it really ends
+        let next = tracker.observe(&status("play", Some("2"), 0.0, 180.0), Some(String::from("b.flac")), after(start, 248.5));
+        assert_eq!(next.as_deref(), Some("a.flac"));
+    }
+
+    #[test]
+    fn pausing_stops_the_estimate() {
+        let start = Instant::now();
+        let mut tracker = playing(start);
+        assert_eq!(tracker.observe(&status("pause", Some("1"), 30.0, 200.0), None, after(start, 30.0)), None);
+        let next = tracker.observe(&status("play", Some("2"), 0.0, 180.0), Some(String::from("b.flac")), after(start, 500.0));
+        assert_eq!(next, None);
+    }
+}
@@ -0,0 +1,385 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use image::{ImageError, RgbImage};
+use image::imageops::{self, FilterType};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tui::Frame;
+use tui::buffer::Buffer;
+use tui::layout::{Alignment, Rect};
+use tui::style::Color;
+use tui::text::Span;
+use tui::widgets::Paragraph;
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::layout::{self, LayoutError};
+use crate::mpd::{MpdClient, MpdError};
+
+/// Decoded covers are shrunk to fit this many pixels a side; terminals never
+/// need more.
+const MAX_SIZE: u32 = 256;
+/// Albums kept decoded before the cache is cleared.
+const CACHE_SIZE: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtMode {
+    /// Two pixels per cell using upper half blocks.
+    #[default]
+    Blocks,
+    /// Eight dots per cell, one color per cell.
+    Braille,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorMode {
+    /// Truecolor if `COLORTERM` says the terminal supports it, 256 colors otherwise.
+    #[default]
+    Auto,
+    Truecolor,
+    Indexed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlbumArtOptions {
+    pub mode: ArtMode,
+    pub color: ColorMode,
+}
+
+/// Covers shared with the threads fetching them.
+#[derive(Default)]
+struct Covers {
+    /// Decoded covers by album, `None` for albums without one.
+    cache: HashMap<String, Option<Arc<RgbImage>>>,
+    /// Albums being fetched.
+    loading: HashSet<String>,
+    /// Albums that could not be fetched, and why.
+    errors: HashMap<String, String>,
+}
+
+/// Shows the current song's cover, from `readpicture` (embedded art) or
+/// `albumart` (a cover file in the song's directory). Covers are fetched and
+/// decoded on their own connection and thread, as large ones take a while.
+pub struct AlbumArtWidget {
+    pane: Pane,
+    options: AlbumArtOptions,
+    covers: Arc<Mutex<Covers>>,
+    /// Cache key of the current song's album, if a song is playing.
+    current: Option<String>,
+    message: String,
+    /// The current cover scaled for the last area it was drawn in.
+    scaled: RefCell<Option<(Rect, Arc<RgbImage>, RgbImage)>>,
+}
+
+impl Widget for AlbumArtWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+        let covers = self.covers.lock().unwrap();
+        let image = match &self.current {
+            Some(key) => match (covers.cache.get(key), covers.errors.get(key)) {
+                (Some(Some(image)), _) => Ok(image.clone()),
+                (Some(None), _) => Err(String::from("No album art")),
+                (None, Some(error)) => Err(error.clone()),
+                (None, None) => Err(String::from("Loading album art")),
+            },
+            None => Err(self.message.clone()),
+        };
+        drop(covers);
+        let image = match image {
+            Ok(image) => image,
+            Err(message) => {
+                let message = Paragraph::new(Span::styled(message, style.text_style))
+                    .alignment(Alignment::Center);
+                f.render_widget(message, inner);
+                return;
+            }
+        };
+        let image = &image;
+        let mut scaled = self.scaled.borrow_mut();
+        let stale = match &*scaled {
+            Some((rect, source, _)) => *rect != inner || !Arc::ptr_eq(source, image),
+            None => true,
+        };
+        if stale {
+            let (width, height) = match self.options.mode {
+                ArtMode::Blocks => (inner.width as u32, inner.height as u32 * 2),
+                ArtMode::Braille => (inner.width as u32 * 2, inner.height as u32 * 4),
+            };
+            *scaled = Some((inner, image.clone(), fit(image, width, height)));
+        }
+        if let Some((_, _, image)) = &*scaled {
+            let truecolor = match self.options.color {
+                ColorMode::Auto => env::var("COLORTERM").map(|term| term == "truecolor" || term == "24bit").unwrap_or(false),
+                ColorMode::Truecolor => true,
+                ColorMode::Indexed => false,
+            };
+            let picture = Picture {
+                image,
+                mode: self.options.mode,
+                truecolor,
+            };
+            f.render_widget(picture, inner);
+        }
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        let mut value = self.pane.to_value("album_art");
+        value["options"] = json!(self.options);
+        value
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        if !subsystems.iter().any(|subsystem| subsystem == "player" || subsystem == "database") {
+            return;
+        }
+        if let Err(err) = self.load_current(mpd, config) {
+            self.current = None;
+            self.message = err.to_string();
+        }
+    }
+
+    fn on_disconnect(&mut self) {
+        self.current = None;
+        self.message = String::from("Disconnected");
+    }
+}
+
+impl Default for AlbumArtWidget {
+    fn default() -> Self {
+        AlbumArtWidget::new(String::from("Album Art"), AlbumArtOptions::default())
+    }
+}
+
+impl AlbumArtWidget {
+    pub fn new(title: String, options: AlbumArtOptions) -> Self {
+        AlbumArtWidget {
+            pane: Pane::new(title),
+            options,
+            covers: Arc::new(Mutex::new(Covers::default())),
+            current: None,
+            message: String::from("Nothing playing"),
+            scaled: RefCell::new(None),
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        let mut widget = AlbumArtWidget::new(String::new(), Self::options(value)?);
+        widget.pane = Pane::from_value(value)?;
+        Ok(widget)
+    }
+
+    pub fn check(value: &Value) -> Result<(), LayoutError> {
+        Self::options(value)?;
+        layout::check_pane(value)
+    }
+
+    fn options(value: &Value) -> Result<AlbumArtOptions, LayoutError> {
+        Ok(match value.get("options") {
+            Some(options) => AlbumArtOptions::deserialize(options)?,
+            None => AlbumArtOptions::default(),
+        })
+    }
+
+    /// Shows the current song's cover, starting to fetch it if it is not
+    /// cached or being fetched already.
+    fn load_current(&mut self, mpd: &mut MpdClient, config: &Config) -> Result<(), MpdError> {
+        let song = mpd.command("currentsong")?;
+        let file = match song.get("file") {
+            Some(file) => file.to_string(),
+            None => {
+                self.current = None;
+                self.message = String::from("Nothing playing");
+                return Ok(());
+            }
+        };
+        mpd.require("albumart", 0, 21)?;
+        let directory = file.rsplit_once('/').map(|(directory, _)| directory).unwrap_or("");
+        let key = format!("{}\n{}", directory, song.get("Album").unwrap_or(""));
+        self.current = Some(key.clone());
+        let mut covers = self.covers.lock().unwrap();
+        // a cover that failed is tried again when the song comes back
+        if covers.cache.contains_key(&key) || !covers.loading.insert(key.clone()) {
+            return Ok(());
+        }
+        covers.errors.remove(&key);
+        let (config, covers) = (config.clone(), self.covers.clone());
+        thread::spawn(move || {
+            let result = fetch(&config, &file);
+            let mut covers = covers.lock().unwrap();
+            covers.loading.remove(&key);
+            match result {
+                Ok(image) => {
+                    if covers.cache.len() >= CACHE_SIZE {
+                        covers.cache.clear();
+                    }
+                    covers.cache.insert(key, image);
+                }
+                Err(err) => {
+                    covers.errors.insert(key, err);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Reads and decodes the cover of `file` on a connection of its own. An
+/// embedded picture that cannot be decoded falls back to the cover file, and
+/// its error is only reported when there is no cover file either.
+fn fetch(config: &Config, file: &str) -> Result<Option<Arc<RgbImage>>, String> {
+    let mut mpd = config.connect().map_err(|err| err.to_string())?;
+    let mut embedded_error = None;
+    // embedded pictures can only be read since 0.22
+    if mpd.at_least(0, 22) {
+        if let Some(data) = mpd.read_picture("readpicture", file).map_err(|err| err.to_string())? {
+            match decode(&data) {
+                Ok(image) => return Ok(Some(image)),
+                Err(err) => embedded_error = Some(err),
+            }
+        }
+    }
+    match mpd.read_picture("albumart", file).map_err(|err| err.to_string())? {
+        Some(data) => decode(&data).map(Some),
+        None => embedded_error.map_or(Ok(None), Err),
+    }
+}
+
+/// Decodes a cover and shrinks it to `MAX_SIZE`. Only png and jpeg covers
+/// can be decoded.
+fn decode(data: &[u8]) -> Result<Arc<RgbImage>, String> {
+    match image::load_from_memory(data) {
+        Ok(image) => Ok(Arc::new(image.thumbnail(MAX_SIZE, MAX_SIZE).to_rgb8())),
+        Err(ImageError::Unsupported(_)) => Err(String::from("Unsupported image format")),
+        Err(err) => Err(format!("Broken album art: {}", err)),
+    }
+}
+
+/// Scales `image` to fit in `width` by `height` pixels, keeping its aspect ratio.
+fn fit(image: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let scale = (width as f32 / image.width() as f32).min(height as f32 / image.height() as f32);
+    let width = ((image.width() as f32 * scale) as u32).max(1);
+    let height = ((image.height() as f32 * scale) as u32).max(1);
+    imageops::resize(image, width, height, FilterType::Triangle)
+}
+
+/// An already scaled image, drawn centered in the area it is rendered to.
+struct Picture<'a> {
+    image: &'a RgbImage,
+    mode: ArtMode,
+    truecolor: bool,
+}
+
+impl Picture<'_> {
+    fn color(&self, [r, g, b]: [u8; 3]) -> Color {
+        if self.truecolor {
+            return Color::Rgb(r, g, b);
+        }
+        // nearest entry of the 6x6x6 cube in the 256 color palette
+        let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+        Color::Indexed(16 + 36 * level(r) + 6 * level(g) + level(b))
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> Option<[u8; 3]> {
+        if x < self.image.width() && y < self.image.height() {
+            Some(self.image.get_pixel(x, y).0)
+        } else {
+            None
+        }
+    }
+}
+
+impl tui::widgets::Widget for Picture<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (dots_x, dots_y) = match self.mode {
+            ArtMode::Blocks => (1, 2),
+            ArtMode::Braille => (2, 4),
+        };
+        let cells_x = self.image.width().div_ceil(dots_x);
+        let cells_y = self.image.height().div_ceil(dots_y);
+        let offset_x = (area.width as u32).saturating_sub(cells_x) / 2;
+        let offset_y = (area.height as u32).saturating_sub(cells_y) / 2;
+        // braille dots are lit where the picture is brighter than average
+        let luma = |[r, g, b]: [u8; 3]| r as u32 * 299 + g as u32 * 587 + b as u32 * 114;
+        let mean = self.image.pixels().map(|pixel| luma(pixel.0) as u64).sum::<u64>()
+            / (self.image.width() * self.image.height()).max(1) as u64;
+        for cy in 0..cells_y.min(area.height as u32) {
+            for cx in 0..cells_x.min(area.width as u32) {
+                let cell = buf.get_mut(area.x + (offset_x + cx) as u16, area.y + (offset_y + cy) as u16);
+                match self.mode {
+                    ArtMode::Blocks => {
+                        let top = self.pixel(cx, cy * 2);
+                        let bottom = self.pixel(cx, cy * 2 + 1);
+                        if let Some(top) = top {
+                            cell.set_symbol("▀").set_fg(self.color(top));
+                        }
+                        if let Some(bottom) = bottom {
+                            cell.set_bg(self.color(bottom));
+                        }
+                    }
+                    ArtMode::Braille => {
+                        // bit for each dot, indexed by [y][x]
+                        const BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+                        let mut bits = 0;
+                        let mut sum = [0u32; 3];
+                        let mut count = 0;
+                        for (dy, row) in BITS.iter().enumerate() {
+                            for (dx, bit) in row.iter().enumerate() {
+                                if let Some(pixel) = self.pixel(cx * 2 + dx as u32, cy * 4 + dy as u32) {
+                                    if luma(pixel) as u64 > mean {
+                                        bits |= bit;
+                                    }
+                                    for (total, channel) in sum.iter_mut().zip(pixel) {
+                                        *total += channel as u32;
+                                    }
+                                    count += 1;
+                                }
+                            }
+                        }
+                        if count > 0 {
+                            let average = sum.map(|total| (total / count) as u8);
+                            let symbol = char::from_u32(0x2800 + bits).unwrap_or(' ');
+                            cell.set_symbol(&symbol.to_string()).set_fg(self.color(average));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undecodable_covers_say_why() {
+        assert_eq!(decode(b"GIF89a\x01\x00\x01\x00").unwrap_err(), "Unsupported image format");
+        assert_eq!(decode(b"RIFF\x10\x00\x00\x00WEBPVP8 ").unwrap_err(), "Unsupported image format");
+        assert!(decode(b"\x89PNG\r\n\x1a\n\x00").unwrap_err().starts_with("Broken album art"));
+    }
+
+    #[test]
+    fn covers_are_shrunk() {
+        let mut data = Vec::new();
+        let image = image::DynamicImage::ImageRgb8(RgbImage::new(1024, 512));
+        image.write_to(&mut std::io::Cursor::new(&mut data), image::ImageOutputFormat::Png).unwrap();
+        let image = decode(&data).unwrap();
+        assert_eq!((image.width(), image.height()), (MAX_SIZE, MAX_SIZE / 2));
+    }
+}
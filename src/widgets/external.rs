@@ -0,0 +1,414 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{self, Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::{Alignment, Rect};
+use tui::style::Style;
+use tui::text::{Span, Spans};
+use tui::widgets::Paragraph;
+use crate::ansi;
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::layout::{self, LayoutError};
+use crate::mpd::{MpdClient, MpdError};
+
+/// Lines of output kept from a running process.
+const MAX_LINES: usize = 1000;
+/// How often a repeated command is checked for having exited.
+const POLL: Duration = Duration::from_millis(20);
+
+/// Shows the output of a shell command. With an interval the command is run
+/// again every `interval_ms` and its latest output shown; without one it is
+/// left running and the newest lines it prints are shown as they come. ANSI
+/// colors are kept. With `stdin` set, keys the app has no use for are sent
+/// to a running command while the pane is focused.
+pub struct ExternalWidget {
+    pane: Pane,
+    command: String,
+    /// Milliseconds between runs, or 0 to keep one process running.
+    interval_ms: u64,
+    stdin: bool,
+    process: Option<Process>,
+}
+
+impl Widget for ExternalWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+        let process = match &self.process {
+            Some(process) => process,
+            None => {
+                let message = Paragraph::new(Span::styled("Edit the text to set a command", style.text_style))
+                    .alignment(Alignment::Center);
+                f.render_widget(message, inner);
+                return;
+            }
+        };
+        let mut body = inner;
+        if let Some(status) = process.status() {
+            body.height -= 1;
+            let bottom = Rect::new(inner.x, inner.y + body.height, inner.width, 1);
+            f.render_widget(Paragraph::new(Span::styled(status, style.text_style)), bottom);
+        }
+        let output = process.output.lock().unwrap();
+        // a running command's newest lines are the ones to see
+        let skip = match self.interval_ms {
+            0 => output.lines.len().saturating_sub(body.height as usize),
+            _ => 0,
+        };
+        let mut sgr = Style::default();
+        let lines = output.lines.iter()
+            .map(|line| ansi::parse_line(line, style.text_style, &mut sgr))
+            .skip(skip)
+            .take(body.height as usize)
+            .collect::<Vec<Spans>>();
+        f.render_widget(Paragraph::new(lines), body);
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        let mut value = self.pane.to_value("external");
+        value["command"] = json!(self.command);
+        value["interval_ms"] = json!(self.interval_ms);
+        value["stdin"] = json!(self.stdin);
+        value
+    }
+
+    fn get_text(&self) -> Option<String> {
+        Some(self.command.clone())
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.command = text.trim().to_string();
+        self.start();
+    }
+
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        if !self.stdin {
+            return Ok(false);
+        }
+        let stdin = match self.process.as_mut().and_then(|process| process.stdin.as_mut()) {
+            Some(stdin) => stdin,
+            None => return Ok(false),
+        };
+        if let Some(bytes) = ansi::key_bytes(key) {
+            // a process that stopped reading shows as exited
+            let _ = stdin.write_all(&bytes).and_then(|_| stdin.flush());
+        }
+        Ok(true)
+    }
+}
+
+impl Default for ExternalWidget {
+    fn default() -> Self {
+        ExternalWidget::new(String::from("External"), String::new(), 0, false)
+    }
+}
+
+impl ExternalWidget {
+    pub fn new(title: String, command: String, interval_ms: u64, stdin: bool) -> Self {
+        let mut widget = ExternalWidget {
+            pane: Pane::new(title),
+            command,
+            interval_ms,
+            stdin,
+            process: None,
+        };
+        widget.start();
+        widget
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        let saved = Self::saved(value)?;
+        let mut widget = ExternalWidget::new(String::new(), saved.command, saved.interval_ms, saved.stdin);
+        widget.pane = Pane::from_value(value)?;
+        Ok(widget)
+    }
+
+    pub fn check(value: &Value) -> Result<(), LayoutError> {
+        Self::saved(value)?;
+        layout::check_pane(value)
+    }
+
+    fn saved(value: &Value) -> Result<Saved, LayoutError> {
+        Ok(Saved::deserialize(value)?)
+    }
+
+    /// Starts the command over, stopping the one running before.
+    fn start(&mut self) {
+        self.process = None;
+        if self.command.is_empty() {
+            return;
+        }
+        self.process = Some(match self.interval_ms {
+            0 => Process::spawn(&self.command, self.stdin),
+            interval => Process::repeat(&self.command, Duration::from_millis(interval)),
+        });
+    }
+}
+
+/// The fields an external widget saves besides its pane.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Saved {
+    command: String,
+    interval_ms: u64,
+    stdin: bool,
+}
+
+#[derive(Default)]
+struct Output {
+    lines: VecDeque<String>,
+    /// Why the command failed, for the bottom line.
+    error: Option<String>,
+}
+
+/// A command running for the widget, and the thread reading its output.
+/// Dropping it stops both.
+struct Process {
+    output: Arc<Mutex<Output>>,
+    /// The process left running, for a widget without an interval.
+    child: Option<Mutex<Child>>,
+    stdin: Option<ChildStdin>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Process {
+    /// Runs `command` once, reading its output as it comes.
+    fn spawn(command: &str, stdin: bool) -> Self {
+        let output = Arc::new(Mutex::new(Output::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let child = shell(command)
+            .stdin(if stdin { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                output.lock().unwrap().error = Some(format!("Could not run {}: {}", command, err));
+                return Process { output, child: None, stdin: None, stop };
+            }
+        };
+        if let Some(stdout) = child.stdout.take() {
+            let output = output.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stdout);
+                let mut line = Vec::new();
+                while matches!(reader.read_until(b'\n', &mut line), Ok(read) if read > 0) {
+                    let mut output = output.lock().unwrap();
+                    if output.lines.len() == MAX_LINES {
+                        output.lines.pop_front();
+                    }
+                    output.lines.push_back(String::from_utf8_lossy(&line).trim_end_matches(['\n', '\r']).to_string());
+                    line.clear();
+                }
+            });
+        }
+        Process {
+            output,
+            stdin: child.stdin.take(),
+            child: Some(Mutex::new(child)),
+            stop,
+        }
+    }
+
+    /// Runs `command` every `interval` until dropped, keeping the output of
+    /// the last run.
+    fn repeat(command: &str, interval: Duration) -> Self {
+        let output = Arc::new(Mutex::new(Output::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (command, shared, stopped) = (command.to_string(), output.clone(), stop.clone());
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                let started = Instant::now();
+                let result = match run_until(&command, &stopped) {
+                    Ok(Some(result)) => Ok(result),
+                    Ok(None) => break,
+                    Err(err) => Err(err),
+                };
+                let mut output = shared.lock().unwrap();
+                match result {
+                    Ok(result) => {
+                        output.lines = String::from_utf8_lossy(&result.stdout).lines().map(String::from).collect();
+                        // the first line of stderr usually says why
+                        output.error = Some(String::from_utf8_lossy(&result.stderr).lines().next()
+                            .map(String::from)
+                            .unwrap_or_else(|| result.status.to_string()))
+                            .filter(|_| !result.status.success());
+                    }
+                    Err(err) => output.error = Some(format!("Could not run {}: {}", command, err)),
+                }
+                drop(output);
+                // checks for the widget going away while waiting
+                while !stopped.load(Ordering::Relaxed) && started.elapsed() < interval {
+                    thread::sleep(interval.saturating_sub(started.elapsed()).min(Duration::from_millis(100)));
+                }
+            }
+        });
+        Process { output, child: None, stdin: None, stop }
+    }
+
+    /// Why the command failed or how it exited, if it did.
+    fn status(&self) -> Option<String> {
+        if let Some(error) = &self.output.lock().unwrap().error {
+            return Some(error.clone());
+        }
+        let mut child = self.child.as_ref()?.lock().unwrap();
+        match child.try_wait() {
+            Ok(Some(status)) => Some(format!("Exited: {}", status)),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(child) = &self.child {
+            kill_group(&mut child.lock().unwrap());
+        }
+    }
+}
+
+/// Runs `command` to the end, collecting its output like
+/// `Command::output`, unless `stop` is set first. Returns `None` if it was
+/// stopped.
+fn run_until(command: &str, stop: &AtomicBool) -> io::Result<Option<process::Output>> {
+    let mut child = shell(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // both pipes are drained at once, so a command filling one is not stuck
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+    let status = loop {
+        if stop.load(Ordering::Relaxed) {
+            kill_group(&mut child);
+            return Ok(None);
+        }
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        thread::sleep(POLL);
+    };
+    // jobs it left in the background would keep the pipes open
+    kill_group(&mut child);
+    Ok(Some(process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }))
+}
+
+fn read_all(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut data = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut data);
+        }
+        data
+    })
+}
+
+/// A shell running `command` in a process group of its own, so what it
+/// starts can be stopped with it.
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command).process_group(0);
+    shell
+}
+
+/// Kills a command started by `shell` and everything else in its process
+/// group, then reaps it.
+fn kill_group(child: &mut Child) {
+    // SAFETY: kill only sends a signal; the group is the one the child leads
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+    use super::*;
+
+    /// Waits up to two seconds for `done`.
+    fn eventually(mut done: impl FnMut() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(2) {
+            if done() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    /// Whether `pid` has exited. Orphans nobody reaps stay as zombies.
+    fn gone(pid: i32) -> bool {
+        match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => stat.rsplit(") ").next().is_some_and(|rest| rest.starts_with('Z')),
+            Err(_) => true,
+        }
+    }
+
+    fn lines(process: &Process) -> Vec<String> {
+        process.output.lock().unwrap().lines.iter().cloned().collect()
+    }
+
+    /// Drops `process`, which should not wait for anything it started.
+    fn stop(process: Process) {
+        let start = Instant::now();
+        drop(process);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn dropping_a_running_command_kills_what_it_started() {
+        let process = Process::spawn("sleep 30 & echo $!; wait", false);
+        assert!(eventually(|| !lines(&process).is_empty()));
+        let pid = lines(&process)[0].parse::<i32>().unwrap();
+        assert!(!gone(pid));
+        stop(process);
+        assert!(eventually(|| gone(pid)));
+    }
+
+    #[test]
+    fn repeated_commands_keep_the_last_output() {
+        let process = Process::repeat("printf 'a\\nb\\n'; echo oops >&2; exit 3", Duration::from_millis(50));
+        assert!(eventually(|| lines(&process) == ["a", "b"]));
+        assert_eq!(process.status().as_deref(), Some("oops"));
+    }
+
+    #[test]
+    fn dropping_a_repeated_command_stops_its_run() {
+        let path = env::temp_dir().join(format!("mpd-thing-rs-external-{}", process::id()));
+        let command = format!("sleep 30 & echo $! > {}; wait", path.display());
+        let process = Process::repeat(&command, Duration::from_secs(60));
+        assert!(eventually(|| fs::read_to_string(&path).is_ok_and(|pid| pid.ends_with('\n'))));
+        let pid = fs::read_to_string(&path).unwrap().trim().parse::<i32>().unwrap();
+        let _ = fs::remove_file(&path);
+        stop(process);
+        assert!(eventually(|| gone(pid)));
+    }
+}
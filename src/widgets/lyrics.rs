@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+use serde_json::Value;
+use tui::Frame;
+use tui::layout::{Alignment, Rect};
+use tui::style::Modifier;
+use tui::text::{Span, Spans};
+use tui::widgets::{Paragraph, Wrap};
+use crate::config::{self, Config};
+use crate::containers::{F, Pane, Widget};
+use crate::layout::LayoutError;
+use crate::mpd::{MpdClient, MpdError, Response};
+
+/// A song's lyrics. Lines of an `.lrc` file carry the time in seconds they
+/// start at; plain text has none.
+#[derive(Default)]
+struct Lyrics {
+    lines: Vec<(Option<f64>, String)>,
+}
+
+impl Lyrics {
+    fn synced(&self) -> bool {
+        self.lines.iter().any(|(time, _)| time.is_some())
+    }
+
+    /// The index of the line being sung at `elapsed` seconds.
+    fn current(&self, elapsed: f64) -> Option<usize> {
+        self.lines.iter().rposition(|(time, _)| matches!(time, Some(time) if *time <= elapsed))
+    }
+}
+
+/// Shows the current song's lyrics from a local `.lrc` or `.txt` file. Timed
+/// lyrics highlight the current line and keep it in the middle of the pane.
+pub struct LyricsWidget {
+    pane: Pane,
+    lyrics: Lyrics,
+    message: String,
+    /// Elapsed time from the last status, and when it was read.
+    elapsed: f64,
+    since: Instant,
+    playing: bool,
+}
+
+impl Widget for LyricsWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.height == 0 {
+            return;
+        }
+        if self.lyrics.lines.is_empty() {
+            let message = Paragraph::new(Span::styled(self.message.clone(), style.text_style))
+                .alignment(Alignment::Center);
+            f.render_widget(message, inner);
+            return;
+        }
+        if !self.lyrics.synced() {
+            let text = self.lyrics.lines.iter()
+                .map(|(_, line)| Spans::from(Span::styled(line.clone(), style.text_style)))
+                .collect::<Vec<Spans>>();
+            let paragraph = Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true });
+            f.render_widget(paragraph, inner);
+            return;
+        }
+        let current = self.lyrics.current(self.elapsed());
+        let start = current.unwrap_or(0).saturating_sub(inner.height as usize / 2);
+        let text = self.lyrics.lines.iter()
+            .enumerate()
+            .skip(start)
+            .take(inner.height as usize)
+            .map(|(index, (_, line))| {
+                let line_style = if Some(index) == current {
+                    style.text_style.add_modifier(Modifier::BOLD | Modifier::REVERSED)
+                } else {
+                    style.text_style
+                };
+                Spans::from(Span::styled(line.clone(), line_style))
+            })
+            .collect::<Vec<Spans>>();
+        f.render_widget(Paragraph::new(text).alignment(Alignment::Center), inner);
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        self.pane.to_value("lyrics")
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        if !subsystems.iter().any(|subsystem| subsystem == "player") {
+            return;
+        }
+        if let Err(err) = self.load_current(mpd, config) {
+            self.lyrics = Lyrics::default();
+            self.message = err.to_string();
+        }
+    }
+
+    fn on_disconnect(&mut self) {
+        self.lyrics = Lyrics::default();
+        self.message = String::from("Disconnected");
+    }
+}
+
+impl Default for LyricsWidget {
+    fn default() -> Self {
+        LyricsWidget::new(String::from("Lyrics"))
+    }
+}
+
+impl LyricsWidget {
+    pub fn new(title: String) -> Self {
+        LyricsWidget {
+            pane: Pane::new(title),
+            lyrics: Lyrics::default(),
+            message: String::from("Nothing playing"),
+            elapsed: 0.0,
+            since: Instant::now(),
+            playing: false,
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        Ok(LyricsWidget {
+            pane: Pane::from_value(value)?,
+            ..LyricsWidget::default()
+        })
+    }
+
+    /// The playback position now, counting time since the last status.
+    fn elapsed(&self) -> f64 {
+        if self.playing {
+            self.elapsed + self.since.elapsed().as_secs_f64()
+        } else {
+            self.elapsed
+        }
+    }
+
+    fn load_current(&mut self, mpd: &mut MpdClient, config: &Config) -> Result<(), MpdError> {
+        let status = mpd.status()?;
+        self.elapsed = status.parse("elapsed").unwrap_or(0.0);
+        self.since = Instant::now();
+        self.playing = status.get("state") == Some("play");
+        let song = mpd.command("currentsong")?;
+        if song.get("file").is_none() {
+            self.lyrics = Lyrics::default();
+            self.message = String::from("Nothing playing");
+            return Ok(());
+        }
+        let text = lyrics_paths(&song, config).iter().find_map(|path| fs::read_to_string(path).ok());
+        match text {
+            Some(text) => self.lyrics = parse_lrc(&text),
+            None => {
+                self.lyrics = Lyrics::default();
+                self.message = String::from("No lyrics found");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where lyrics for `song` may be, in the order they are tried.
+fn lyrics_paths(song: &Response, config: &Config) -> Vec<PathBuf> {
+    let file = PathBuf::from(song.get("file").unwrap_or(""));
+    let mut paths = Vec::new();
+    if let Some(music_directory) = &config.music_directory {
+        let song_path = config::expand_home(music_directory).join(&file);
+        paths.push(song_path.with_extension("lrc"));
+        paths.push(song_path.with_extension("txt"));
+    }
+    if let Some(lyrics_dir) = &config.lyrics_dir {
+        let lyrics_dir = config::expand_home(lyrics_dir);
+        if let (Some(artist), Some(title)) = (song.get("Artist"), song.get("Title")) {
+            // file names cannot contain slashes
+            let name = format!("{} - {}", artist, title).replace('/', "_");
+            paths.push(lyrics_dir.join(format!("{}.lrc", name)));
+            paths.push(lyrics_dir.join(format!("{}.txt", name)));
+        }
+        if let Some(name) = file.file_name() {
+            let song_path = lyrics_dir.join(name);
+            paths.push(song_path.with_extension("lrc"));
+            paths.push(song_path.with_extension("txt"));
+        }
+    }
+    paths
+}
+
+/// Parses LRC lyrics. A line may start with several `[mm:ss.xx]` timestamps;
+/// other tags such as `[ar:...]` are dropped, except `[offset:ms]`. Text
+/// without timestamps is kept as plain lines.
+fn parse_lrc(text: &str) -> Lyrics {
+    let mut lines = Vec::new();
+    let mut offset = 0.0;
+    for line in text.lines() {
+        let mut rest = line.trim_end();
+        let mut times = Vec::new();
+        let mut tagged = false;
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|tag| tag.split_once(']')) {
+            if let Some(time) = parse_timestamp(tag) {
+                times.push(time);
+            } else if let Some(ms) = tag.strip_prefix("offset:") {
+                // a positive offset shows lyrics sooner
+                offset = ms.trim().parse::<f64>().unwrap_or(0.0) / 1000.0;
+            }
+            tagged = true;
+            rest = after;
+        }
+        if times.is_empty() {
+            if !tagged {
+                lines.push((None, rest.to_string()));
+            }
+            continue;
+        }
+        for time in times {
+            lines.push((Some(time), rest.trim().to_string()));
+        }
+    }
+    if lines.iter().any(|(time, _)| time.is_some()) {
+        // untimed lines cannot be placed in a synced file
+        lines.retain(|(time, _)| time.is_some());
+        for (time, _) in lines.iter_mut() {
+            *time = time.map(|time| (time - offset).max(0.0));
+        }
+        lines.sort_by(|(a, _), (b, _)| a.unwrap_or(0.0).total_cmp(&b.unwrap_or(0.0)));
+    }
+    Lyrics {
+        lines,
+    }
+}
+
+/// Parses `mm:ss`, `mm:ss.xx` or `mm:ss:xx` into seconds. Seconds that are
+/// are not finite or are negative make the tag malformed.
+fn parse_timestamp(tag: &str) -> Option<f64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes = minutes.parse::<u32>().ok()?;
+    let seconds = seconds.replacen(':', ".", 1).parse::<f64>().ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)?;
+    Some(minutes as f64 * 60.0 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(lyrics: &Lyrics) -> Vec<(Option<f64>, &str)> {
+        lyrics.lines.iter().map(|(time, text)| (*time, text.as_str())).collect()
+    }
+
+    #[test]
+    fn several_timestamps_repeat_a_line() {
+        let lyrics = parse_lrc("[00:10.00][01:00.50]Chorus\n[00:20]Verse\n");
+        assert_eq!(times(&lyrics), [(Some(10.0), "Chorus"), (Some(20.0), "Verse"), (Some(60.5), "Chorus")]);
+    }
+
+    #[test]
+    fn offset_shifts_lines_sooner() {
+        let lyrics = parse_lrc("[offset:500]\n[00:00.20]First\n[00:02:00]Second\n");
+        assert_eq!(times(&lyrics), [(Some(0.0), "First"), (Some(1.5), "Second")]);
+        let lyrics = parse_lrc("[offset:-1000]\n[00:01]Late\n");
+        assert_eq!(times(&lyrics), [(Some(2.0), "Late")]);
+    }
+
+    #[test]
+    fn malformed_tags_are_dropped() {
+        let lyrics = parse_lrc("[ar:Someone]\n[00:1x]Broken\n[00:05]Kept\n[00:06 unclosed\n[offset:soon]\n");
+        assert_eq!(times(&lyrics), [(Some(5.0), "Kept")]);
+        let lyrics = parse_lrc("[00:nan]Odd\n[00:inf]Far\n[00:-1]Early\n[00:03]Fine\n");
+        assert_eq!(times(&lyrics), [(Some(3.0), "Fine")]);
+    }
+
+    #[test]
+    fn untimed_text_stays_plain() {
+        let lyrics = parse_lrc("[ti:Song]\nFirst line\n\nSecond line\n");
+        assert_eq!(times(&lyrics), [(None, "First line"), (None, ""), (None, "Second line")]);
+        assert!(!lyrics.synced());
+    }
+}
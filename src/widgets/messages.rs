@@ -0,0 +1,254 @@
+use std::collections::VecDeque;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::{Alignment, Rect};
+use tui::style::Modifier;
+use tui::text::{Span, Spans};
+use tui::widgets::Paragraph;
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::editor::TextEditor;
+use crate::layout::{self, LayoutError};
+use crate::mpd::{quote, MpdClient, MpdError};
+use crate::widgets::connected;
+
+/// Messages kept; older ones are dropped.
+const MAX_MESSAGES: usize = 1000;
+
+/// What the bottom line is being typed for.
+enum Prompt {
+    Subscribe,
+    Send,
+}
+
+/// Client-to-client messages. Shows what arrives on the subscribed channels
+/// and sends to the selected one. `a` subscribes, `d` unsubscribes, left and
+/// right pick a channel, Enter writes a message and `x` clears the log.
+pub struct MessagesWidget {
+    pane: Pane,
+    /// Channels subscribed to, kept in the layout.
+    channels: Vec<String>,
+    /// The channel messages are sent to.
+    selected: usize,
+    /// Channels anyone on the server is subscribed to.
+    server_channels: Vec<String>,
+    /// Channel and message, newest last.
+    log: VecDeque<(String, String)>,
+    prompt: Option<(Prompt, TextEditor)>,
+    message: String,
+}
+
+impl Widget for MessagesWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.width == 0 || inner.height < 3 {
+            return;
+        }
+        let mut header = vec![Span::styled("Channels:", style.title_style)];
+        for (index, channel) in self.channels.iter().enumerate() {
+            let channel_style = if index == self.selected {
+                style.text_style.add_modifier(Modifier::REVERSED)
+            } else {
+                style.text_style
+            };
+            header.push(Span::raw(" "));
+            header.push(Span::styled(channel.clone(), channel_style));
+        }
+        let others = self.server_channels.iter()
+            .filter(|channel| !self.channels.contains(channel))
+            .cloned()
+            .collect::<Vec<String>>();
+        if !others.is_empty() {
+            header.push(Span::styled(format!("  (also on the server: {})", others.join(", ")), style.text_style.add_modifier(Modifier::DIM)));
+        }
+        f.render_widget(Paragraph::new(Spans::from(header)), Rect { height: 1, ..inner });
+
+        let log_area = Rect::new(inner.x, inner.y + 1, inner.width, inner.height - 2);
+        if self.log.is_empty() {
+            let text = if self.channels.is_empty() { "Press 'a' to subscribe to a channel" } else { "No messages" };
+            let message = Paragraph::new(Span::styled(text, style.text_style))
+                .alignment(Alignment::Center);
+            f.render_widget(message, log_area);
+        } else {
+            let lines = self.log.iter()
+                .skip(self.log.len().saturating_sub(log_area.height as usize))
+                .map(|(channel, message)| Spans::from(vec![
+                    Span::styled(format!("{}: ", channel), style.text_style.add_modifier(Modifier::BOLD)),
+                    Span::styled(message.clone(), style.text_style),
+                ]))
+                .collect::<Vec<Spans>>();
+            f.render_widget(Paragraph::new(lines), log_area);
+        }
+
+        let bottom = Rect::new(inner.x, inner.y + inner.height - 1, inner.width, 1);
+        match &self.prompt {
+            Some((prompt, editor)) => {
+                let label = match prompt {
+                    Prompt::Subscribe => String::from("Subscribe to: "),
+                    Prompt::Send => format!("To {}: ", self.channels[self.selected]),
+                };
+                editor.draw_prompt(f, bottom, &label, style.title_style, style.text_style);
+            }
+            None => f.render_widget(Paragraph::new(Span::styled(self.message.clone(), style.text_style)), bottom),
+        }
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        let mut value = self.pane.to_value("messages");
+        value["channels"] = json!(self.channels);
+        value
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        if !subsystems.iter().any(|subsystem| subsystem == "subscription") {
+            return;
+        }
+        match mpd.command("channels") {
+            Ok(response) => {
+                self.server_channels = response.pairs.into_iter()
+                    .filter(|(key, _)| key == "channel")
+                    .map(|(_, channel)| channel)
+                    .collect();
+                self.message.clear();
+            }
+            Err(err) => self.message = err.to_string(),
+        }
+    }
+
+    fn on_disconnect(&mut self) {
+        self.server_channels.clear();
+        self.message = String::from("Disconnected");
+    }
+
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        if let Some((prompt, editor)) = &mut self.prompt {
+            match key {
+                Key::Esc => self.prompt = None,
+                Key::Char('\n') => {
+                    let text = editor.text();
+                    let prompt = self.prompt.take().map(|(prompt, _)| prompt);
+                    match prompt {
+                        Some(Prompt::Subscribe) => self.subscribe(text.trim()),
+                        Some(Prompt::Send) => {
+                            let channel = &self.channels[self.selected];
+                            connected(mpd)?.command(&format!("sendmessage {} {}", quote(channel), quote(&text)))?;
+                        }
+                        None => {}
+                    }
+                }
+                _ => {
+                    editor.handle_key(key);
+                }
+            }
+            return Ok(true);
+        }
+        match key {
+            Key::Char('a') => self.prompt = Some((Prompt::Subscribe, TextEditor::new("", false))),
+            Key::Char('d') => {
+                if self.selected < self.channels.len() {
+                    let channel = self.channels.remove(self.selected);
+                    self.selected = self.selected.min(self.channels.len().saturating_sub(1));
+                    self.message = format!("Unsubscribed from {}", channel);
+                }
+            }
+            Key::Left | Key::Char('h') => self.selected = self.selected.saturating_sub(1),
+            Key::Right | Key::Char('l') => self.selected = (self.selected + 1).min(self.channels.len().saturating_sub(1)),
+            Key::Char('\n') | Key::Char('m') => {
+                if self.channels.is_empty() {
+                    self.message = String::from("Subscribe to a channel first");
+                } else {
+                    self.prompt = Some((Prompt::Send, TextEditor::new("", false)));
+                }
+            }
+            Key::Char('x') => self.log.clear(),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn is_editing(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    fn channels(&self) -> Vec<String> {
+        self.channels.clone()
+    }
+
+    fn on_message(&mut self, channel: &str, message: &str) {
+        if !self.channels.iter().any(|subscribed| subscribed == channel) {
+            return;
+        }
+        if self.log.len() == MAX_MESSAGES {
+            self.log.pop_front();
+        }
+        self.log.push_back((channel.to_string(), message.to_string()));
+    }
+}
+
+impl Default for MessagesWidget {
+    fn default() -> Self {
+        MessagesWidget::new(String::from("Messages"), Vec::new())
+    }
+}
+
+impl MessagesWidget {
+    pub fn new(title: String, channels: Vec<String>) -> Self {
+        MessagesWidget {
+            pane: Pane::new(title),
+            channels,
+            selected: 0,
+            server_channels: Vec::new(),
+            log: VecDeque::new(),
+            prompt: None,
+            message: String::new(),
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        let mut widget = MessagesWidget::new(String::new(), Self::saved_channels(value)?);
+        widget.pane = Pane::from_value(value)?;
+        Ok(widget)
+    }
+
+    pub fn check(value: &Value) -> Result<(), LayoutError> {
+        Self::saved_channels(value)?;
+        layout::check_pane(value)
+    }
+
+    fn saved_channels(value: &Value) -> Result<Vec<String>, LayoutError> {
+        Ok(match value.get("channels") {
+            Some(channels) => Vec::<String>::deserialize(channels)?,
+            None => Vec::new(),
+        })
+    }
+
+    /// Adds `channel` to the channels the idle connection subscribes to.
+    fn subscribe(&mut self, channel: &str) {
+        if channel.is_empty() {
+            return;
+        }
+        // the characters MPD allows in channel names
+        if !channel.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')) {
+            self.message = format!("Invalid channel name: {}", channel);
+            return;
+        }
+        match self.channels.iter().position(|subscribed| subscribed == channel) {
+            Some(index) => self.selected = index,
+            None => {
+                self.channels.push(channel.to_string());
+                self.selected = self.channels.len() - 1;
+                self.message = format!("Subscribed to {}", channel);
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::io;
+use crate::mpd::{MpdClient, MpdError};
+
+pub mod album_art;
+pub mod external;
+pub mod lyrics;
+pub mod messages;
+pub mod outputs;
+pub mod partitions;
+pub mod queue;
+pub mod script;
+pub mod song_info;
+pub mod stats;
+pub mod terminal;
+pub mod visualizer;
+
+/// The client passed to `Widget::handle_key`, or an error if MPD could not
+/// be reached.
+pub fn connected(mpd: Option<&mut MpdClient>) -> Result<&mut MpdClient, MpdError> {
+    mpd.ok_or_else(|| MpdError::Io(io::Error::new(io::ErrorKind::NotConnected, "not connected to MPD")))
+}
+
+/// Turns a rejected command into `None`, keeping connection errors. Some
+/// commands fail for reasons that only mean there is nothing to show, like
+/// `readcomments` on a stream.
+pub fn optional<T>(result: Result<T, MpdError>) -> Result<Option<T>, MpdError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(MpdError::Server { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,431 @@
+use serde_json::Value;
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::{Alignment, Rect};
+use tui::style::Modifier;
+use tui::text::{Span, Spans};
+use tui::widgets::Paragraph;
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::editor::TextEditor;
+use crate::layout::LayoutError;
+use crate::mpd::{quote, MpdClient, MpdError};
+use crate::widgets::connected;
+
+const REPLAY_GAIN_MODES: &[&str] = &["off", "track", "album", "auto"];
+/// Width of the labels in front of the mixer settings.
+const LABEL_WIDTH: usize = 14;
+
+struct Output {
+    id: u32,
+    name: String,
+    plugin: String,
+    enabled: bool,
+    /// Plugin specific settings, changed with `outputset`.
+    attributes: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Mixer {
+    /// `None` if MPD has no mixer.
+    volume: Option<u32>,
+    replay_gain: String,
+    crossfade: u32,
+    mixramp_db: f64,
+    /// `None` if MixRamp is off.
+    mixramp_delay: Option<f64>,
+}
+
+/// An attribute value being typed in. The output and attribute are kept by
+/// id and name, as the rows move when outputs change while typing.
+struct AttributeEdit {
+    output: u32,
+    name: String,
+    editor: TextEditor,
+}
+
+/// A selectable line of the widget.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Output(usize),
+    /// An attribute of an output, by output and attribute index.
+    Attribute(usize, usize),
+    Volume,
+    ReplayGain,
+    Crossfade,
+    MixrampDb,
+    MixrampDelay,
+}
+
+/// Lists MPD's audio outputs and the mixer settings. Enter or space toggles
+/// an output, `e` and `d` enable and disable it, and Enter on an attribute
+/// flips it or opens it for editing. Left and right change the setting on
+/// the selected line.
+pub struct OutputsWidget {
+    pane: Pane,
+    outputs: Vec<Output>,
+    mixer: Mixer,
+    selected: usize,
+    /// The attribute value being edited.
+    edit: Option<AttributeEdit>,
+    message: String,
+    /// What the last key did, for the bottom bar.
+    status: Option<String>,
+}
+
+impl Widget for OutputsWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+        if !self.message.is_empty() {
+            let message = Paragraph::new(Span::styled(self.message.clone(), style.text_style))
+                .alignment(Alignment::Center);
+            f.render_widget(message, inner);
+            return;
+        }
+        let rows = self.rows();
+        let height = inner.height as usize;
+        let start = (self.selected + 1).saturating_sub(height);
+        for (line, (index, row)) in rows.iter().enumerate().skip(start).take(height).enumerate() {
+            let area = Rect::new(inner.x, inner.y + line as u16, inner.width, 1);
+            let selected = index == self.selected;
+            let line_style = if selected {
+                style.text_style.add_modifier(Modifier::REVERSED)
+            } else {
+                style.text_style
+            };
+            if let (true, Some(edit)) = (selected, &self.edit) {
+                let label = format!("    {} = ", edit.name);
+                let width = (label.chars().count() as u16).min(area.width);
+                f.render_widget(Paragraph::new(Span::styled(label, style.text_style)), Rect { width, ..area });
+                let rest = Rect::new(area.x + width, area.y, area.width - width, 1);
+                edit.editor.draw(f, rest, line_style, true);
+                continue;
+            }
+            let text = self.row_text(*row, inner.width as usize);
+            f.render_widget(Paragraph::new(Spans::from(Span::styled(text, line_style))), area);
+        }
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        self.pane.to_value("outputs")
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        if !subsystems.iter().any(|subsystem| matches!(subsystem.as_str(), "output" | "mixer" | "options")) {
+            return;
+        }
+        if let Err(err) = self.refresh(mpd) {
+            self.message = err.to_string();
+        }
+    }
+
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        if let Some(edit) = &mut self.edit {
+            match key {
+                Key::Esc => self.edit = None,
+                Key::Char('\n') => {
+                    if let Some(edit) = self.edit.take() {
+                        self.submit(mpd, edit)?;
+                    }
+                }
+                _ => {
+                    edit.editor.handle_key(key);
+                }
+            }
+            return Ok(true);
+        }
+        let rows = self.rows();
+        match key {
+            Key::Up | Key::Char('k') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.selected = (self.selected + 1).min(rows.len().saturating_sub(1)),
+            Key::Char('\n') | Key::Char(' ') | Key::Char('e') | Key::Char('d') | Key::Left | Key::Right | Key::Char('h') | Key::Char('l') => {
+                let row = match rows.get(self.selected) {
+                    Some(row) => *row,
+                    None => return Ok(true),
+                };
+                let client = connected(mpd)?;
+                self.activate(client, config, row, key)?;
+                self.refresh(client)?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn is_editing(&self) -> bool {
+        self.edit.is_some()
+    }
+
+    fn take_status(&mut self) -> Option<String> {
+        self.status.take()
+    }
+
+    fn on_disconnect(&mut self) {
+        self.outputs.clear();
+        self.edit = None;
+        self.message = String::from("Disconnected");
+    }
+}
+
+impl Default for OutputsWidget {
+    fn default() -> Self {
+        OutputsWidget::new(String::from("Outputs"))
+    }
+}
+
+impl OutputsWidget {
+    pub fn new(title: String) -> Self {
+        OutputsWidget {
+            pane: Pane::new(title),
+            outputs: Vec::new(),
+            mixer: Mixer::default(),
+            selected: 0,
+            edit: None,
+            message: String::from("Not connected"),
+            status: None,
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        Ok(OutputsWidget {
+            pane: Pane::from_value(value)?,
+            ..OutputsWidget::default()
+        })
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for (index, output) in self.outputs.iter().enumerate() {
+            rows.push(Row::Output(index));
+            rows.extend((0..output.attributes.len()).map(|attribute| Row::Attribute(index, attribute)));
+        }
+        rows.extend([Row::Volume, Row::ReplayGain, Row::Crossfade, Row::MixrampDb, Row::MixrampDelay]);
+        rows
+    }
+
+    /// The row of the attribute `name` of the output with id `output`.
+    fn attribute_row(&self, output: u32, name: &str) -> Option<usize> {
+        self.rows().iter().position(|row| match *row {
+            Row::Attribute(index, attribute) => {
+                let output_at = &self.outputs[index];
+                output_at.id == output && output_at.attributes[attribute].0 == name
+            }
+            _ => false,
+        })
+    }
+
+    fn row_text(&self, row: Row, width: usize) -> String {
+        let setting = |label: &str, value: String| format!("{:<width$}{}", label, value, width = LABEL_WIDTH);
+        match row {
+            Row::Output(index) => {
+                let output = &self.outputs[index];
+                format!("[{}] {} ({})", if output.enabled { 'x' } else { ' ' }, output.name, output.plugin)
+            }
+            Row::Attribute(output, attribute) => {
+                let (name, value) = &self.outputs[output].attributes[attribute];
+                format!("    {} = {}", name, value)
+            }
+            Row::Volume => match self.mixer.volume {
+                Some(volume) => {
+                    // room for the label, brackets and percentage
+                    let bar = width.saturating_sub(LABEL_WIDTH + 7);
+                    let filled = bar * volume as usize / 100;
+                    setting("Volume", format!("[{}{}] {:>3}%", "█".repeat(filled), "░".repeat(bar - filled), volume))
+                }
+                None => setting("Volume", String::from("no mixer")),
+            },
+            Row::ReplayGain => setting("Replay gain", self.mixer.replay_gain.clone()),
+            Row::Crossfade => setting("Crossfade", format!("{}s", self.mixer.crossfade)),
+            Row::MixrampDb => setting("MixRamp", format!("{} dB", self.mixer.mixramp_db)),
+            Row::MixrampDelay => match self.mixer.mixramp_delay {
+                Some(delay) => setting("MixRamp delay", format!("{}s", delay)),
+                None => setting("MixRamp delay", String::from("off")),
+            },
+        }
+    }
+
+    fn refresh(&mut self, mpd: &mut MpdClient) -> Result<(), MpdError> {
+        self.outputs = mpd.command("outputs")?.records("outputid").iter()
+            .map(|record| Output {
+                id: record.parse("outputid").unwrap_or(0),
+                name: record.get("outputname").unwrap_or("").to_string(),
+                plugin: record.get("plugin").unwrap_or("").to_string(),
+                enabled: record.get("outputenabled") == Some("1"),
+                attributes: record.pairs.iter()
+                    .filter(|(key, _)| key == "attribute")
+                    .filter_map(|(_, value)| value.split_once('='))
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            })
+            .collect();
+        let status = mpd.status()?;
+        let replay_gain = mpd.command("replay_gain_status")?;
+        self.mixer = Mixer {
+            // -1 means there is no mixer
+            volume: status.parse::<i64>("volume").filter(|volume| *volume >= 0).map(|volume| volume as u32),
+            replay_gain: replay_gain.get("replay_gain_mode").unwrap_or("off").to_string(),
+            crossfade: status.parse("xfade").unwrap_or(0),
+            mixramp_db: status.parse("mixrampdb").unwrap_or(0.0),
+            mixramp_delay: status.parse::<f64>("mixrampdelay").filter(|delay| delay.is_finite() && *delay >= 0.0),
+        };
+        // the attribute being edited stays selected wherever it moved
+        if let Some(row) = self.edit.as_ref().and_then(|edit| self.attribute_row(edit.output, &edit.name)) {
+            self.selected = row;
+        }
+        self.selected = self.selected.min(self.rows().len() - 1);
+        self.message.clear();
+        Ok(())
+    }
+
+    /// Sets the attribute that was edited. Its output may have been removed
+    /// or changed while typing.
+    fn submit(&mut self, mpd: Option<&mut MpdClient>, edit: AttributeEdit) -> Result<(), MpdError> {
+        if self.attribute_row(edit.output, &edit.name).is_none() {
+            self.status = Some(format!("Output {} no longer has the attribute {}", edit.output, edit.name));
+            return Ok(());
+        }
+        let mpd = connected(mpd)?;
+        set_attribute(mpd, edit.output, &edit.name, &edit.editor.text())?;
+        self.refresh(mpd)
+    }
+
+    /// Applies `key` to the setting on `row`.
+    fn activate(&mut self, mpd: &mut MpdClient, config: &Config, row: Row, key: Key) -> Result<(), MpdError> {
+        let up = match key {
+            Key::Left | Key::Char('h') => Some(false),
+            Key::Right | Key::Char('l') => Some(true),
+            _ => None,
+        };
+        let step = |up: bool, step: i64| if up { step } else { -step };
+        match (row, key, up) {
+            (Row::Output(index), Key::Char('e'), _) => {
+                mpd.command(&format!("enableoutput {}", self.outputs[index].id))?;
+            }
+            (Row::Output(index), Key::Char('d'), _) => {
+                mpd.command(&format!("disableoutput {}", self.outputs[index].id))?;
+            }
+            (Row::Output(index), Key::Char('\n') | Key::Char(' '), _) => {
+                mpd.command(&format!("toggleoutput {}", self.outputs[index].id))?;
+            }
+            (Row::Attribute(output, attribute), Key::Char('\n') | Key::Char(' '), _) => {
+                // flags are flipped in place, anything else is typed in
+                let output = &self.outputs[output];
+                let (name, value) = &output.attributes[attribute];
+                match value.as_str() {
+                    "0" => set_attribute(mpd, output.id, name, "1")?,
+                    "1" => set_attribute(mpd, output.id, name, "0")?,
+                    value => {
+                        self.edit = Some(AttributeEdit {
+                            output: output.id,
+                            name: name.clone(),
+                            editor: TextEditor::new(value, false),
+                        });
+                    }
+                }
+            }
+            (Row::Volume, _, Some(up)) => {
+                if let Some(volume) = self.mixer.volume {
+                    let volume = (volume as i64 + step(up, config.volume_step as i64)).clamp(0, 100);
+                    mpd.command(&format!("setvol {}", volume))?;
+                }
+            }
+            (Row::ReplayGain, Key::Char('\n') | Key::Char(' '), _) | (Row::ReplayGain, _, Some(_)) => {
+                let current = REPLAY_GAIN_MODES.iter().position(|mode| *mode == self.mixer.replay_gain).unwrap_or(0);
+                let next = match up {
+                    Some(false) => (current + REPLAY_GAIN_MODES.len() - 1) % REPLAY_GAIN_MODES.len(),
+                    _ => (current + 1) % REPLAY_GAIN_MODES.len(),
+                };
+                mpd.command(&format!("replay_gain_mode {}", REPLAY_GAIN_MODES[next]))?;
+            }
+            (Row::Crossfade, _, Some(up)) => {
+                let crossfade = (self.mixer.crossfade as i64 + step(up, config.crossfade_step as i64)).max(0);
+                mpd.command(&format!("crossfade {}", crossfade))?;
+            }
+            (Row::MixrampDb, _, Some(up)) => {
+                mpd.command(&format!("mixrampdb {}", self.mixer.mixramp_db + step(up, 1) as f64))?;
+            }
+            (Row::MixrampDelay, _, Some(up)) => {
+                // going below zero turns MixRamp off
+                let delay = self.mixer.mixramp_delay.unwrap_or(-1.0) + step(up, 1) as f64;
+                if delay < 0.0 {
+                    mpd.command("mixrampdelay nan")?;
+                } else {
+                    mpd.command(&format!("mixrampdelay {}", delay))?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Sets the attribute `name` of the output with id `output`.
+fn set_attribute(mpd: &mut MpdClient, output: u32, name: &str, value: &str) -> Result<(), MpdError> {
+    mpd.require("outputset", 0, 21)?;
+    mpd.command(&format!("outputset {} {} {}", output, quote(name), quote(value)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::Shutdown;
+    use super::*;
+
+    const PIPE: &str = "outputid: 1\noutputname: Pipe\nplugin: pipe\noutputenabled: 1\nattribute: command=cat\n";
+
+    /// The responses to a refresh listing `outputs`.
+    fn refreshed(outputs: &str) -> String {
+        format!("{}OK\nvolume: 50\nOK\nreplay_gain_mode: off\nOK\n", outputs)
+    }
+
+    /// Opens the editor on the pipe's `command`, below an ALSA output, then
+    /// has the outputs change to `outputs` while typing `x`. `after` answers
+    /// what is sent on Enter.
+    fn edit_while_outputs_change(outputs: &str, after: &str) -> (OutputsWidget, String) {
+        let (mut mpd, mut server) = MpdClient::pair("0.23.5");
+        let alsa = "outputid: 0\noutputname: ALSA\nplugin: alsa\noutputenabled: 1\nattribute: dop=x\n";
+        let before = refreshed(&format!("{}{}", alsa, PIPE));
+        write!(server, "{}{}{}", before, before, refreshed(outputs)).unwrap();
+        let mut widget = OutputsWidget::default();
+        widget.refresh(&mut mpd).unwrap();
+        widget.selected = 3;
+        widget.handle_key(Key::Char('\n'), Some(&mut mpd), &Config::default()).unwrap();
+        assert!(widget.is_editing());
+        widget.on_idle(&mut mpd, &Config::default(), &[String::from("output")]);
+        write!(server, "{}", after).unwrap();
+        server.shutdown(Shutdown::Write).unwrap();
+        for key in [Key::End, Key::Char('x'), Key::Char('\n')] {
+            widget.handle_key(key, Some(&mut mpd), &Config::default()).unwrap();
+        }
+        drop(mpd);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).unwrap();
+        (widget, sent)
+    }
+
+    #[test]
+    fn edited_attributes_follow_their_output() {
+        let (mut widget, sent) = edit_while_outputs_change(PIPE, &format!("OK\n{}", refreshed(PIPE)));
+        assert!(sent.contains("outputset 1 \"command\" \"catx\"\n"), "{}", sent);
+        assert_eq!(widget.take_status(), None);
+    }
+
+    #[test]
+    fn edited_attributes_of_removed_outputs_are_reported() {
+        let (mut widget, sent) = edit_while_outputs_change("", "");
+        assert!(!sent.contains("outputset"), "{}", sent);
+        assert_eq!(widget.take_status().as_deref(), Some("Output 1 no longer has the attribute command"));
+    }
+}
@@ -0,0 +1,244 @@
+use serde_json::Value;
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::{Alignment, Rect};
+use tui::style::Modifier;
+use tui::text::{Span, Spans};
+use tui::widgets::Paragraph;
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::editor::TextEditor;
+use crate::layout::LayoutError;
+use crate::mpd::{quote, MpdClient, MpdError};
+use crate::widgets::connected;
+
+struct Partition {
+    name: String,
+    /// Names of the outputs playing in this partition, and whether each is
+    /// enabled.
+    outputs: Vec<(String, bool)>,
+}
+
+/// A selectable line: a partition, or an output in one.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Partition(usize),
+    Output(usize, usize),
+}
+
+/// Lists MPD's partitions with the outputs in each. Enter on a partition
+/// makes this pane control it, `m` moves the selected output here, `n`
+/// creates a partition and `d` deletes the selected one.
+pub struct PartitionsWidget {
+    pane: Pane,
+    partitions: Vec<Partition>,
+    /// The partition the pane controls, as the connection was in when the
+    /// list was loaded.
+    home: String,
+    selected: usize,
+    /// The name of a new partition being typed.
+    editor: Option<TextEditor>,
+    message: String,
+}
+
+impl Widget for PartitionsWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+        let mut list_area = inner;
+        if let Some(editor) = &self.editor {
+            list_area.height -= 1;
+            let bottom = Rect::new(inner.x, inner.y + list_area.height, inner.width, 1);
+            editor.draw_prompt(f, bottom, "New partition: ", style.title_style, style.text_style);
+        }
+        if self.partitions.is_empty() {
+            let message = Paragraph::new(Span::styled(self.message.clone(), style.text_style))
+                .alignment(Alignment::Center);
+            f.render_widget(message, list_area);
+            return;
+        }
+        let rows = self.rows();
+        let height = list_area.height as usize;
+        let start = (self.selected + 1).saturating_sub(height);
+        let lines = rows.iter()
+            .enumerate()
+            .skip(start)
+            .take(height)
+            .map(|(index, row)| {
+                let mut line_style = style.text_style;
+                if index == self.selected {
+                    line_style = line_style.add_modifier(Modifier::REVERSED);
+                }
+                let text = match *row {
+                    Row::Partition(partition) => {
+                        let name = &self.partitions[partition].name;
+                        if *name == self.home {
+                            line_style = line_style.add_modifier(Modifier::BOLD);
+                            format!("* {}", name)
+                        } else {
+                            format!("  {}", name)
+                        }
+                    }
+                    Row::Output(partition, output) => {
+                        let (name, enabled) = &self.partitions[partition].outputs[output];
+                        format!("      [{}] {}", if *enabled { "x" } else { " " }, name)
+                    }
+                };
+                Spans::from(Span::styled(text, line_style))
+            })
+            .collect::<Vec<Spans>>();
+        f.render_widget(Paragraph::new(lines), list_area);
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        self.pane.to_value("partitions")
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        if !subsystems.iter().any(|subsystem| subsystem == "partition" || subsystem == "output") {
+            return;
+        }
+        if let Err(err) = self.refresh(mpd) {
+            self.partitions.clear();
+            self.message = err.to_string();
+        }
+    }
+
+    fn on_disconnect(&mut self) {
+        self.partitions.clear();
+        self.editor = None;
+        self.message = String::from("Disconnected");
+    }
+
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        if let Some(editor) = &mut self.editor {
+            match key {
+                Key::Esc => self.editor = None,
+                Key::Char('\n') => {
+                    let name = editor.text().trim().to_string();
+                    self.editor = None;
+                    if !name.is_empty() {
+                        let client = connected(mpd)?;
+                        client.require("newpartition", 0, 22)?;
+                        client.command(&format!("newpartition {}", quote(&name)))?;
+                        self.refresh(client)?;
+                    }
+                }
+                _ => {
+                    editor.handle_key(key);
+                }
+            }
+            return Ok(true);
+        }
+        let rows = self.rows();
+        let row = rows.get(self.selected).copied();
+        match (key, row) {
+            (Key::Up | Key::Char('k'), _) => self.selected = self.selected.saturating_sub(1),
+            (Key::Down | Key::Char('j'), _) => self.selected = (self.selected + 1).min(rows.len().saturating_sub(1)),
+            (Key::Char('n'), _) => self.editor = Some(TextEditor::new("", false)),
+            (Key::Char('\n'), Some(Row::Partition(partition))) => {
+                let name = self.partitions[partition].name.clone();
+                // the app enters the pane's partition before every use, so
+                // entering it here only marks it as home in the reloaded list
+                let client = connected(mpd)?;
+                client.enter_partition(&name)?;
+                self.pane.partition = Some(name).filter(|name| name != config.partition_name());
+                self.refresh(client)?;
+            }
+            (Key::Char('m'), Some(Row::Output(partition, output))) => {
+                let client = connected(mpd)?;
+                client.require("moveoutput", 0, 22)?;
+                let name = &self.partitions[partition].outputs[output].0;
+                client.command(&format!("moveoutput {}", quote(name)))?;
+                self.refresh(client)?;
+            }
+            (Key::Char('d'), Some(Row::Partition(partition))) => {
+                let client = connected(mpd)?;
+                client.require("delpartition", 0, 23)?;
+                client.command(&format!("delpartition {}", quote(&self.partitions[partition].name)))?;
+                self.refresh(client)?;
+            }
+            (Key::Char('\n') | Key::Char('m') | Key::Char('d'), _) => {}
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn is_editing(&self) -> bool {
+        self.editor.is_some()
+    }
+}
+
+impl Default for PartitionsWidget {
+    fn default() -> Self {
+        PartitionsWidget::new(String::from("Partitions"))
+    }
+}
+
+impl PartitionsWidget {
+    pub fn new(title: String) -> Self {
+        PartitionsWidget {
+            pane: Pane::new(title),
+            partitions: Vec::new(),
+            home: String::new(),
+            selected: 0,
+            editor: None,
+            message: String::from("Not connected"),
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        Ok(PartitionsWidget {
+            pane: Pane::from_value(value)?,
+            ..PartitionsWidget::default()
+        })
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for (index, partition) in self.partitions.iter().enumerate() {
+            rows.push(Row::Partition(index));
+            rows.extend((0..partition.outputs.len()).map(|output| Row::Output(index, output)));
+        }
+        rows
+    }
+
+    /// Reads every partition's outputs. Each partition lists all outputs,
+    /// with the ones playing elsewhere as `dummy` placeholders, so only the
+    /// others are kept. The connection is left in the partition it was in.
+    fn refresh(&mut self, mpd: &mut MpdClient) -> Result<(), MpdError> {
+        let home = mpd.partition().to_string();
+        let result = self.load(mpd);
+        mpd.enter_partition(&home)?;
+        self.partitions = result?;
+        self.home = home;
+        self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+        Ok(())
+    }
+
+    fn load(&mut self, mpd: &mut MpdClient) -> Result<Vec<Partition>, MpdError> {
+        let mut partitions = Vec::new();
+        for name in mpd.partitions()? {
+            mpd.enter_partition(&name)?;
+            let outputs = mpd.command("outputs")?.records("outputid").iter()
+                .filter(|output| output.get("plugin") != Some("dummy"))
+                .map(|output| (output.get("outputname").unwrap_or("").to_string(), output.get("outputenabled") == Some("1")))
+                .collect();
+            partitions.push(Partition {
+                name,
+                outputs,
+            });
+        }
+        Ok(partitions)
+    }
+}
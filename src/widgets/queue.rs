@@ -0,0 +1,461 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use serde_json::Value;
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::{Alignment, Constraint, Rect};
+use tui::style::Modifier;
+use tui::text::Span;
+use tui::widgets::{Paragraph, Row, Table};
+use crate::actions;
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::editor::TextEditor;
+use crate::layout::LayoutError;
+use crate::mpd::{quote, Batch, MpdClient, MpdError, Response};
+use crate::stickers::{self, MAX_RATING};
+use crate::widgets::{connected, optional};
+
+/// Width of the length column.
+const TIME_WIDTH: u16 = 7;
+/// Width of the priority column, shown when a song has a priority.
+const PRIO_WIDTH: u16 = 3;
+
+struct Song {
+    id: u32,
+    file: String,
+    /// `Artist - Title`, or the file name for untagged songs.
+    name: String,
+    duration: Option<f64>,
+    /// Songs with a higher priority are played first in random mode.
+    prio: u8,
+}
+
+/// What the bottom line is being typed for.
+enum Prompt {
+    Priority,
+    /// The part of the song to play, as `start:end` in seconds.
+    Range,
+    /// A song to add after the selection.
+    Add,
+}
+
+impl Prompt {
+    fn label(&self) -> &'static str {
+        match self {
+            Prompt::Priority => "Priority (0-255): ",
+            Prompt::Range => "Play range (start:end): ",
+            Prompt::Add => "Add after: ",
+        }
+    }
+}
+
+impl Song {
+    fn from_record(record: &Response) -> Option<Self> {
+        let file = record.get("file")?.to_string();
+        let name = match (record.get("Artist"), record.get("Title")) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.to_string(),
+            _ => file.rsplit('/').next().unwrap_or(&file).to_string(),
+        };
+        Some(Song {
+            id: record.parse("Id")?,
+            file,
+            name,
+            duration: record.parse("duration").or_else(|| record.parse("Time")),
+            prio: record.parse("Prio").unwrap_or(0),
+        })
+    }
+}
+
+/// The play queue with each song's rating. Enter plays the selected song and
+/// the number keys rate it, 0 removing the rating.
+///
+/// `V` starts selecting a block of songs, like Vim's visual line mode, and
+/// Esc ends it. `P` sets the priority of the selection, `x` shuffles it and
+/// `d` removes it. `r` limits the song under the cursor to a part of it, `a`
+/// adds a song, or every song in a directory, after the cursor and `f`
+/// updates the directory the song under the cursor is in.
+pub struct QueueWidget {
+    pane: Pane,
+    songs: Vec<Song>,
+    /// Ratings by URI, from the sticker database.
+    ratings: HashMap<String, u8>,
+    /// Id of the song playing.
+    current: Option<u32>,
+    /// The cursor.
+    selected: usize,
+    /// Where the visual selection started, if one is being made.
+    anchor: Option<usize>,
+    prompt: Option<(Prompt, TextEditor)>,
+    /// Rows that fit in the pane when it was last drawn, for paging.
+    height: Cell<usize>,
+    message: String,
+    /// What the last key did, for the bottom bar.
+    status: Option<String>,
+}
+
+impl Widget for QueueWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        let mut list_area = inner;
+        if let (Some((prompt, editor)), true) = (&self.prompt, inner.height > 0) {
+            list_area.height -= 1;
+            let bottom = Rect::new(inner.x, inner.y + list_area.height, inner.width, 1);
+            editor.draw_prompt(f, bottom, prompt.label(), style.title_style, style.text_style);
+        }
+        self.height.set(list_area.height as usize);
+        if self.songs.is_empty() {
+            let message = Paragraph::new(Span::styled(self.message.clone(), style.text_style))
+                .alignment(Alignment::Center);
+            f.render_widget(message, list_area);
+            return;
+        }
+        let height = list_area.height as usize;
+        let start = (self.selected + 1).saturating_sub(height);
+        let (first, last) = self.selection();
+        let pos_width = self.songs.len().to_string().len() as u16;
+        let prio_width = if self.songs.iter().any(|song| song.prio > 0) { PRIO_WIDTH } else { 0 };
+        let rows = self.songs.iter()
+            .enumerate()
+            .skip(start)
+            .take(height)
+            .map(|(index, song)| {
+                let mut row_style = style.text_style;
+                if Some(song.id) == self.current {
+                    row_style = row_style.add_modifier(Modifier::BOLD);
+                }
+                if (first..=last).contains(&index) {
+                    row_style = row_style.add_modifier(Modifier::REVERSED);
+                }
+                if self.anchor.is_some() && index == self.selected {
+                    row_style = row_style.add_modifier(Modifier::UNDERLINED);
+                }
+                let rating = self.ratings.get(&song.file).map(|rating| stickers::stars(*rating)).unwrap_or_default();
+                let prio = if song.prio > 0 { song.prio.to_string() } else { String::new() };
+                Row::new(vec![
+                    (index + 1).to_string(),
+                    prio,
+                    song.name.clone(),
+                    song.duration.map(format_time).unwrap_or_default(),
+                    rating,
+                ]).style(row_style)
+            })
+            .collect::<Vec<Row>>();
+        let fixed = pos_width + prio_width + TIME_WIDTH + MAX_RATING as u16 + 4;
+        let widths = [
+            Constraint::Length(pos_width),
+            Constraint::Length(prio_width),
+            Constraint::Length(list_area.width.saturating_sub(fixed)),
+            Constraint::Length(TIME_WIDTH),
+            Constraint::Length(MAX_RATING as u16),
+        ];
+        f.render_widget(Table::new(rows).widths(&widths), list_area);
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        self.pane.to_value("queue")
+    }
+
+    fn is_editing(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    fn take_status(&mut self) -> Option<String> {
+        self.status.take()
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        let changed = |name: &str| subsystems.iter().any(|subsystem| subsystem == name);
+        let result: Result<(), MpdError> = (|| {
+            if changed("playlist") {
+                self.load_queue(mpd)?;
+            }
+            if changed("player") {
+                self.current = mpd.status()?.parse("songid");
+            }
+            if changed("sticker") {
+                self.load_ratings(mpd)?;
+            }
+            Ok(())
+        })();
+        if let Err(err) = result {
+            self.songs.clear();
+            self.message = err.to_string();
+        }
+    }
+
+    fn selected_song(&self) -> Option<String> {
+        self.songs.get(self.selected).map(|song| song.file.clone())
+    }
+
+    fn on_disconnect(&mut self) {
+        self.songs.clear();
+        self.anchor = None;
+        self.prompt = None;
+        self.message = String::from("Disconnected");
+    }
+
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        if let Some((_, editor)) = &mut self.prompt {
+            match key {
+                Key::Esc => self.prompt = None,
+                Key::Char('\n') => {
+                    let text = editor.text().trim().to_string();
+                    if let Some((prompt, _)) = self.prompt.take() {
+                        self.submit(connected(mpd)?, prompt, &text)?;
+                    }
+                }
+                _ => {
+                    editor.handle_key(key);
+                }
+            }
+            return Ok(true);
+        }
+        let page = self.height.get().max(1);
+        let last = self.songs.len().saturating_sub(1);
+        match key {
+            Key::Up | Key::Char('k') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.selected = (self.selected + 1).min(last),
+            Key::PageUp => self.selected = self.selected.saturating_sub(page),
+            Key::PageDown => self.selected = (self.selected + page).min(last),
+            Key::Home => self.selected = 0,
+            Key::End => self.selected = last,
+            Key::Char('\n') => {
+                if let Some(song) = self.songs.get(self.selected) {
+                    connected(mpd)?.command(&format!("playid {}", song.id))?;
+                }
+            }
+            Key::Char('f') => {
+                // streams are not in the database
+                if let Some(song) = self.songs.get(self.selected).filter(|song| !song.file.contains("://")) {
+                    let directory = song.file.rsplit_once('/').map(|(directory, _)| directory);
+                    actions::update_database(connected(mpd)?, false, directory)?;
+                }
+            }
+            Key::Char('V') => {
+                self.anchor = match self.anchor {
+                    Some(_) => None,
+                    None if self.songs.is_empty() => None,
+                    None => Some(self.selected),
+                };
+            }
+            Key::Esc if self.anchor.is_some() => self.anchor = None,
+            Key::Char('P') if !self.songs.is_empty() => self.prompt = Some((Prompt::Priority, TextEditor::new("", false))),
+            Key::Char('r') if !self.songs.is_empty() => self.prompt = Some((Prompt::Range, TextEditor::new("", false))),
+            Key::Char('a') => self.prompt = Some((Prompt::Add, TextEditor::new("", false))),
+            Key::Char('x') if !self.songs.is_empty() => {
+                let (first, last) = self.selection();
+                connected(mpd)?.command(&format!("shuffle {}:{}", first, last + 1))?;
+            }
+            Key::Char('d') | Key::Delete if !self.songs.is_empty() => {
+                let (first, last) = self.selection();
+                let mut batch = Batch::new();
+                for song in &self.songs[first..=last] {
+                    batch.push(format!("deleteid {}", song.id));
+                }
+                self.status = Some(connected(mpd)?.run_batch(&batch)?.summary("Removed", "songs"));
+                self.anchor = None;
+                self.selected = first;
+            }
+            Key::Char(digit @ '0'..='9') => {
+                let rating = digit.to_digit(10).unwrap() as u8;
+                if rating > MAX_RATING {
+                    return Ok(false);
+                }
+                if let Some(song) = self.songs.get(self.selected) {
+                    let client = connected(mpd)?;
+                    stickers::rate(client, &song.file, rating)?;
+                    self.load_ratings(client)?;
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+impl Default for QueueWidget {
+    fn default() -> Self {
+        QueueWidget::new(String::from("Queue"))
+    }
+}
+
+impl QueueWidget {
+    pub fn new(title: String) -> Self {
+        QueueWidget {
+            pane: Pane::new(title),
+            songs: Vec::new(),
+            ratings: HashMap::new(),
+            current: None,
+            selected: 0,
+            anchor: None,
+            prompt: None,
+            height: Cell::new(0),
+            message: String::from("Not connected"),
+            status: None,
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        Ok(QueueWidget {
+            pane: Pane::from_value(value)?,
+            ..QueueWidget::default()
+        })
+    }
+
+    fn load_queue(&mut self, mpd: &mut MpdClient) -> Result<(), MpdError> {
+        let queue = mpd.command("playlistinfo")?;
+        self.songs = queue.records("file").iter().filter_map(Song::from_record).collect();
+        self.selected = self.selected.min(self.songs.len().saturating_sub(1));
+        self.anchor = self.anchor.filter(|anchor| *anchor < self.songs.len());
+        self.message = String::from("The queue is empty");
+        Ok(())
+    }
+
+    /// The first and last position of the visual selection, or the cursor
+    /// if there is none.
+    fn selection(&self) -> (usize, usize) {
+        match self.anchor {
+            Some(anchor) => (anchor.min(self.selected), anchor.max(self.selected)),
+            None => (self.selected, self.selected),
+        }
+    }
+
+    /// Runs what the prompt was for. MPD checks the values typed in. The
+    /// queue may have changed while the prompt was open, so the songs it was
+    /// opened for can be gone.
+    fn submit(&mut self, mpd: &mut MpdClient, prompt: Prompt, text: &str) -> Result<(), MpdError> {
+        match prompt {
+            Prompt::Priority => {
+                mpd.require("prio", 0, 17)?;
+                let (first, last) = self.selection();
+                let songs = match self.songs.get(first..=last) {
+                    Some(songs) => songs,
+                    None => {
+                        self.status = Some(String::from("The selected songs are no longer in the queue"));
+                        return Ok(());
+                    }
+                };
+                let mut batch = Batch::new();
+                for song in songs {
+                    batch.push(format!("prioid {} {}", quote(text), song.id));
+                }
+                self.status = Some(mpd.run_batch(&batch)?.summary("Set the priority of", "songs"));
+            }
+            Prompt::Range => {
+                mpd.require("rangeid", 0, 19)?;
+                let id = match self.songs.get(self.selected) {
+                    Some(song) => song.id,
+                    None => {
+                        self.status = Some(String::from("The song is no longer in the queue"));
+                        return Ok(());
+                    }
+                };
+                let range = if text.is_empty() { ":" } else { text };
+                mpd.command(&format!("rangeid {} {}", id, quote(range)))?;
+            }
+            Prompt::Add => {
+                if text.is_empty() {
+                    return Ok(());
+                }
+                // an empty queue can only be added to at its start
+                let position = if self.songs.is_empty() { 0 } else { self.selected + 1 };
+                // a directory adds every song in it; anything else, like a
+                // stream, is added as it is
+                let uris = optional(mpd.command(&format!("listall {}", quote(text))))?
+                    .map(|listing| listing.pairs.into_iter()
+                        .filter(|(key, _)| key == "file")
+                        .map(|(_, uri)| uri)
+                        .collect::<Vec<_>>())
+                    .filter(|uris| !uris.is_empty())
+                    .unwrap_or_else(|| vec![text.to_string()]);
+                self.status = Some(mpd.add_all(&uris, Some(position))?.summary("Added", "songs"));
+                self.selected = position;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads every rating at once. Without a sticker database there are none.
+    fn load_ratings(&mut self, mpd: &mut MpdClient) -> Result<(), MpdError> {
+        self.ratings = optional(mpd.find_stickers(stickers::RATING))?
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(uri, value)| Some((uri, stickers::parse_rating(&value)?)))
+            .collect();
+        Ok(())
+    }
+}
+
+/// Formats a song length as `m:ss`, or `h:mm:ss` for long ones.
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use super::*;
+
+    fn queue(count: u32) -> QueueWidget {
+        QueueWidget {
+            songs: (0..count)
+                .map(|id| Song { id, file: format!("{}.flac", id), name: id.to_string(), duration: None, prio: 0 })
+                .collect(),
+            ..QueueWidget::default()
+        }
+    }
+
+    fn type_text(queue: &mut QueueWidget, text: &str, mpd: &mut MpdClient) {
+        for c in text.chars().chain(['\n']) {
+            queue.handle_key(Key::Char(c), Some(&mut *mpd), &Config::default()).unwrap();
+        }
+    }
+
+    #[test]
+    fn prompts_survive_the_queue_emptying() {
+        let (mut mpd, _server) = MpdClient::pair("0.23.5");
+        for key in ['P', 'r'] {
+            let mut queue = queue(3);
+            queue.selected = 2;
+            queue.handle_key(Key::Char(key), None, &Config::default()).unwrap();
+            assert!(queue.is_editing());
+            // the queue was cleared by another client while typing
+            queue.songs.clear();
+            type_text(&mut queue, "5", &mut mpd);
+            assert!(!queue.is_editing());
+            assert!(queue.take_status().unwrap().contains("no longer in the queue"));
+        }
+    }
+
+    #[test]
+    fn priority_covers_the_visual_selection() {
+        let (mut mpd, mut server) = MpdClient::pair("0.23.5");
+        let mut queue = queue(4);
+        queue.selected = 1;
+        for key in [Key::Char('V'), Key::Down, Key::Char('P')] {
+            queue.handle_key(key, None, &Config::default()).unwrap();
+        }
+        writeln!(server, "list_OK\nlist_OK\nOK").unwrap();
+        type_text(&mut queue, "9", &mut mpd);
+        assert_eq!(queue.take_status().as_deref(), Some("Set the priority of 2 songs"));
+        drop(mpd);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).unwrap();
+        assert_eq!(sent, "command_list_ok_begin\nprioid \"9\" 1\nprioid \"9\" 2\ncommand_list_end\n");
+    }
+}
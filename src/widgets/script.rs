@@ -0,0 +1,187 @@
+use rhai::Dynamic;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::{Alignment, Rect};
+use tui::text::{Span, Spans};
+use tui::widgets::{Paragraph, Wrap};
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::layout::{self, LayoutError};
+use crate::mpd::{MpdClient, MpdError};
+use crate::script::{self, Script};
+
+/// A widget drawn by a script in the scripts directory. The script's
+/// functions, all optional and all called with the script's state as
+/// `this`, are:
+///
+/// - `init()` once it is loaded,
+/// - `on_idle(subsystems)` when MPD reports changes,
+/// - `on_key(key)` with keys while focused, returning whether it used them,
+/// - `render()` every frame, returning a string or an array of lines.
+///
+/// MPD can be reached from `on_idle` and `on_key`. The script's name is the
+/// widget's text, so it can be changed in Edit mode.
+pub struct ScriptWidget {
+    pane: Pane,
+    /// Name of the script, without `.rhai`.
+    name: String,
+    script: Option<Script>,
+    /// The last error, or what the script printed, for the bottom line.
+    message: String,
+}
+
+impl Widget for ScriptWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+        let mut body = inner;
+        if !self.message.is_empty() {
+            body.height -= 1;
+            let bottom = Rect::new(inner.x, inner.y + body.height, inner.width, 1);
+            f.render_widget(Paragraph::new(Span::styled(self.message.clone(), style.text_style)), bottom);
+        }
+        let script = match &self.script {
+            Some(script) => script,
+            None => return,
+        };
+        match script.call("render", Vec::new(), None) {
+            Ok(rendered) => {
+                let lines = lines(rendered).into_iter()
+                    .map(|line| Spans::from(Span::styled(line, style.text_style)))
+                    .collect::<Vec<Spans>>();
+                f.render_widget(Paragraph::new(lines), body);
+            }
+            Err(err) => {
+                let error = Paragraph::new(Span::styled(err, style.text_style))
+                    .alignment(Alignment::Center)
+                    .wrap(Wrap { trim: true });
+                f.render_widget(error, body);
+            }
+        }
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        let mut value = self.pane.to_value("script");
+        value["script"] = json!(self.name);
+        value
+    }
+
+    fn get_text(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.load(text.trim());
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        let subsystems = subsystems.iter().cloned().map(Dynamic::from).collect::<Vec<Dynamic>>();
+        let _ = self.call("on_idle", vec![subsystems.into()], Some(mpd));
+    }
+
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        let name = match script::key_name(key) {
+            Some(name) => name,
+            None => return Ok(false),
+        };
+        Ok(self.call("on_key", vec![name.into()], mpd).as_bool().unwrap_or(false))
+    }
+}
+
+impl Default for ScriptWidget {
+    fn default() -> Self {
+        ScriptWidget::new(String::from("Script"), "")
+    }
+}
+
+impl ScriptWidget {
+    pub fn new(title: String, name: &str) -> Self {
+        let mut widget = ScriptWidget {
+            pane: Pane::new(title),
+            name: String::new(),
+            script: None,
+            message: String::new(),
+        };
+        widget.load(name);
+        widget
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        let mut widget = ScriptWidget::new(String::new(), &Self::saved_script(value)?);
+        widget.pane = Pane::from_value(value)?;
+        Ok(widget)
+    }
+
+    pub fn check(value: &Value) -> Result<(), LayoutError> {
+        Self::saved_script(value)?;
+        layout::check_pane(value)
+    }
+
+    fn saved_script(value: &Value) -> Result<String, LayoutError> {
+        Ok(match value.get("script") {
+            Some(name) => String::deserialize(name)?,
+            None => String::new(),
+        })
+    }
+
+    /// Loads the script called `name`. A script that fails to load leaves
+    /// the widget showing why.
+    fn load(&mut self, name: &str) {
+        self.name = name.to_string();
+        self.script = None;
+        if name.is_empty() {
+            self.message = format!("Edit the text to name a script in {}", script::scripts_dir().display());
+            return;
+        }
+        match Script::load(name) {
+            Ok(script) => {
+                self.message = script.take_printed().unwrap_or_default();
+                self.script = Some(script);
+            }
+            Err(err) => self.message = err,
+        }
+    }
+
+    /// Calls a function of the script, keeping an error or what it printed
+    /// for the bottom line.
+    fn call(&mut self, function: &str, args: Vec<Dynamic>, mpd: Option<&mut MpdClient>) -> Dynamic {
+        let script = match &self.script {
+            Some(script) => script,
+            None => return Dynamic::UNIT,
+        };
+        let result = script.call(function, args, mpd);
+        if let Some(printed) = script.take_printed() {
+            self.message = printed;
+        }
+        match result {
+            Ok(result) => result,
+            Err(err) => {
+                self.message = err;
+                Dynamic::UNIT
+            }
+        }
+    }
+}
+
+/// What `render` returned, as lines.
+fn lines(rendered: Dynamic) -> Vec<String> {
+    if rendered.is_array() {
+        rendered.into_array().unwrap_or_default().into_iter().map(|line| line.to_string()).collect()
+    } else if rendered.is_unit() {
+        Vec::new()
+    } else {
+        rendered.to_string().lines().map(String::from).collect()
+    }
+}
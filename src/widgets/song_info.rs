@@ -0,0 +1,246 @@
+use std::cell::Cell;
+use serde_json::Value;
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::{Alignment, Constraint, Rect};
+use tui::text::Span;
+use tui::widgets::{Cell as TableCell, Paragraph, Row, Table};
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::layout::LayoutError;
+use crate::mpd::{quote, MpdClient, MpdError, Response};
+use crate::widgets::optional;
+
+/// Widest the key column gets; longer keys are cut off.
+const MAX_KEY_WIDTH: u16 = 24;
+
+/// A line of the table: a section heading, or a key and its value.
+enum Line {
+    Heading(&'static str),
+    Field(String, String),
+}
+
+/// Lists everything MPD knows about the song selected in a song list such as
+/// the queue, or the current song if none is: its tags, the raw comments in
+/// the file, and how it is being played.
+pub struct SongInfoWidget {
+    pane: Pane,
+    /// URI of the song selected in a song list.
+    selected: Option<String>,
+    lines: Vec<Line>,
+    message: String,
+    /// First line shown.
+    offset: usize,
+    /// Lines that fit in the pane when it was last drawn, for paging.
+    height: Cell<usize>,
+}
+
+impl Widget for SongInfoWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        self.height.set(inner.height as usize);
+        if self.lines.is_empty() {
+            let message = Paragraph::new(Span::styled(self.message.clone(), style.text_style))
+                .alignment(Alignment::Center);
+            f.render_widget(message, inner);
+            return;
+        }
+        let key_width = self.lines.iter()
+            .map(|line| match line {
+                Line::Heading(_) => 0,
+                Line::Field(key, _) => key.chars().count() as u16,
+            })
+            .max()
+            .unwrap_or(0)
+            .min(MAX_KEY_WIDTH);
+        let rows = self.lines.iter()
+            .skip(self.offset)
+            .map(|line| match line {
+                Line::Heading(heading) => Row::new(vec![TableCell::from(*heading)]).style(style.title_style),
+                Line::Field(key, value) => Row::new(vec![key.as_str(), value.as_str()]).style(style.text_style),
+            })
+            .collect::<Vec<Row>>();
+        let widths = [Constraint::Length(key_width), Constraint::Length(inner.width.saturating_sub(key_width + 2))];
+        let table = Table::new(rows)
+            .widths(&widths)
+            .column_spacing(2);
+        f.render_widget(table, inner);
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        self.pane.to_value("song_info")
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        if !subsystems.iter().any(|subsystem| matches!(subsystem.as_str(), "player" | "database" | "sticker")) {
+            return;
+        }
+        self.reload(mpd);
+    }
+
+    fn on_song_selected(&mut self, mpd: &mut MpdClient, config: &Config, file: Option<&str>) {
+        self.selected = file.map(str::to_string);
+        self.reload(mpd);
+    }
+
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        let page = self.height.get().max(1);
+        let last = self.lines.len().saturating_sub(page);
+        self.offset = match key {
+            Key::Up | Key::Char('k') => self.offset.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.offset + 1,
+            Key::PageUp => self.offset.saturating_sub(page),
+            Key::PageDown => self.offset + page,
+            Key::Home => 0,
+            Key::End => last,
+            _ => return Ok(false),
+        }.min(last);
+        Ok(true)
+    }
+
+    fn on_disconnect(&mut self) {
+        self.lines.clear();
+        self.message = String::from("Disconnected");
+    }
+}
+
+impl Default for SongInfoWidget {
+    fn default() -> Self {
+        SongInfoWidget::new(String::from("Song Info"))
+    }
+}
+
+impl SongInfoWidget {
+    pub fn new(title: String) -> Self {
+        SongInfoWidget {
+            pane: Pane::new(title),
+            selected: None,
+            lines: Vec::new(),
+            message: String::from("Nothing playing"),
+            offset: 0,
+            height: Cell::new(0),
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        Ok(SongInfoWidget {
+            pane: Pane::from_value(value)?,
+            ..SongInfoWidget::default()
+        })
+    }
+
+    fn reload(&mut self, mpd: &mut MpdClient) {
+        if let Err(err) = self.load(mpd) {
+            self.lines.clear();
+            self.message = err.to_string();
+        }
+    }
+
+    /// Loads the selected song, or the current one.
+    fn load(&mut self, mpd: &mut MpdClient) -> Result<(), MpdError> {
+        let current = mpd.command("currentsong")?;
+        let (song, playing) = match &self.selected {
+            Some(file) if current.get("file") != Some(file.as_str()) => {
+                // songs outside the database, like streams, have no tags
+                let song = optional(mpd.command(&format!("lsinfo {}", quote(file))))?
+                    .unwrap_or_else(|| Response { pairs: vec![(String::from("file"), file.clone())], binary: None });
+                (song, false)
+            }
+            _ => (current, true),
+        };
+        let file = match song.get("file") {
+            Some(file) => file.to_string(),
+            None => {
+                self.lines.clear();
+                self.message = String::from("Nothing playing");
+                return Ok(());
+            }
+        };
+        let mut lines = vec![Line::Heading("Tags")];
+        lines.extend(song.pairs.iter().map(|(key, value)| Line::Field(key.clone(), value.clone())));
+        // lsinfo adds what the queue entry leaves out, like the audio format
+        // of songs that have not been played yet; a selected song came from
+        // lsinfo already
+        if playing {
+            let info = optional(mpd.command(&format!("lsinfo {}", quote(&file))))?;
+            for (key, value) in info.iter().flat_map(|info| &info.pairs) {
+                if song.get(key).is_none() {
+                    lines.push(Line::Field(key.clone(), value.clone()));
+                }
+            }
+        }
+        if let Some(comments) = optional(mpd.command(&format!("readcomments {}", quote(&file))))? {
+            if !comments.pairs.is_empty() {
+                lines.push(Line::Heading("Comments"));
+                lines.extend(comments.pairs.into_iter().map(|(key, value)| Line::Field(key, value)));
+            }
+        }
+        if let Some(stickers) = optional(mpd.command(&format!("sticker list song {}", quote(&file))))? {
+            if !stickers.pairs.is_empty() {
+                lines.push(Line::Heading("Stickers"));
+                lines.extend(stickers.pairs.iter()
+                    .filter_map(|(_, sticker)| sticker.split_once('='))
+                    .map(|(name, value)| Line::Field(name.to_string(), value.to_string())));
+            }
+        }
+        if !playing {
+            self.lines = lines;
+            self.offset = 0;
+            return Ok(());
+        }
+        lines.push(Line::Heading("Playback"));
+        let status = mpd.status()?;
+        for key in ["audio", "bitrate", "elapsed", "duration"] {
+            if let Some(value) = status.get(key) {
+                lines.push(Line::Field(key.to_string(), value.to_string()));
+            }
+        }
+        if let Some(replay_gain) = optional(mpd.command("replay_gain_status"))? {
+            if let Some(mode) = replay_gain.get("replay_gain_mode") {
+                lines.push(Line::Field(String::from("replay_gain_mode"), mode.to_string()));
+            }
+        }
+        self.lines = lines;
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::Shutdown;
+    use super::*;
+
+    #[test]
+    fn a_selected_song_is_looked_up_once() {
+        let (mut mpd, mut server) = MpdClient::pair("0.23.5");
+        // currentsong, lsinfo, readcomments and sticker list
+        write!(server, "file: b.flac\nOK\nfile: a.flac\nTitle: A\nFormat: 44100:16:2\nOK\nOK\nOK\n").unwrap();
+        server.shutdown(Shutdown::Write).unwrap();
+        let mut widget = SongInfoWidget {
+            selected: Some(String::from("a.flac")),
+            ..SongInfoWidget::default()
+        };
+        widget.load(&mut mpd).unwrap();
+        drop(mpd);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).unwrap();
+        assert_eq!(sent.matches("lsinfo").count(), 1, "{}", sent);
+        let fields = widget.lines.iter()
+            .filter_map(|line| match line {
+                Line::Field(key, _) => Some(key.as_str()),
+                Line::Heading(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(fields, ["file", "Title", "Format"]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+use tui::Frame;
+use tui::layout::{Alignment, Constraint, Rect};
+use tui::text::Span;
+use tui::widgets::{Cell, Paragraph, Row, Table};
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::layout::LayoutError;
+use crate::mpd::{MpdClient, MpdError, Response};
+
+/// Width of the label column.
+const LABEL_WIDTH: u16 = 14;
+
+/// A summary of `stats` and `status`: library size, uptime, what is being
+/// played and any database update or error. Only refreshed when MPD reports
+/// a change; uptime counts on its own in between.
+pub struct StatsWidget {
+    pane: Pane,
+    stats: Response,
+    status: Response,
+    /// When `stats` was read, to keep uptime current.
+    fetched: Instant,
+    message: String,
+}
+
+impl Widget for StatsWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if !self.message.is_empty() {
+            let message = Paragraph::new(Span::styled(self.message.clone(), style.text_style))
+                .alignment(Alignment::Center);
+            f.render_widget(message, inner);
+            return;
+        }
+        let rows = self.lines().into_iter()
+            .map(|(label, value)| match value {
+                Some(value) => Row::new(vec![Cell::from(label), Cell::from(value)]).style(style.text_style),
+                None => Row::new(vec![Cell::from(label)]).style(style.title_style),
+            })
+            .collect::<Vec<Row>>();
+        let widths = [Constraint::Length(LABEL_WIDTH), Constraint::Length(inner.width.saturating_sub(LABEL_WIDTH + 1))];
+        f.render_widget(Table::new(rows).widths(&widths), inner);
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        self.pane.to_value("stats")
+    }
+
+    fn on_idle(&mut self, mpd: &mut MpdClient, config: &Config, subsystems: &[String]) {
+        if !subsystems.iter().any(|subsystem| matches!(subsystem.as_str(), "database" | "update" | "player")) {
+            return;
+        }
+        if let Err(err) = self.refresh(mpd) {
+            self.message = err.to_string();
+        }
+    }
+
+    fn on_disconnect(&mut self) {
+        self.message = String::from("Disconnected");
+    }
+}
+
+impl Default for StatsWidget {
+    fn default() -> Self {
+        StatsWidget::new(String::from("Stats"))
+    }
+}
+
+impl StatsWidget {
+    pub fn new(title: String) -> Self {
+        StatsWidget {
+            pane: Pane::new(title),
+            stats: Response::default(),
+            status: Response::default(),
+            fetched: Instant::now(),
+            message: String::from("Not connected"),
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        Ok(StatsWidget {
+            pane: Pane::from_value(value)?,
+            ..StatsWidget::default()
+        })
+    }
+
+    fn refresh(&mut self, mpd: &mut MpdClient) -> Result<(), MpdError> {
+        self.stats = mpd.command("stats")?;
+        self.status = mpd.status()?;
+        self.fetched = Instant::now();
+        self.message.clear();
+        Ok(())
+    }
+
+    /// Labels and values to show. Headings have no value.
+    fn lines(&self) -> Vec<(&'static str, Option<String>)> {
+        let stat = |key: &str| self.stats.get(key).unwrap_or("0").to_string();
+        let seconds = |key: &str| self.stats.parse::<u64>(key).unwrap_or(0);
+        let mut lines = vec![
+            ("Library", None),
+            ("Artists", Some(stat("artists"))),
+            ("Albums", Some(stat("albums"))),
+            ("Songs", Some(stat("songs"))),
+            ("Play time", Some(format_duration(seconds("db_playtime")))),
+        ];
+        let updated = match self.stats.parse::<u64>("db_update") {
+            Some(timestamp) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0);
+                format!("{} ago", format_duration(now.saturating_sub(timestamp)))
+            }
+            None => String::from("never"),
+        };
+        lines.push(("Last update", Some(updated)));
+        if let Some(job) = self.status.get("updating_db") {
+            lines.push(("Updating", Some(format!("job {}", job))));
+        }
+        lines.push(("Server", None));
+        let uptime = seconds("uptime") + self.fetched.elapsed().as_secs();
+        lines.push(("Uptime", Some(format_duration(uptime))));
+        lines.push(("Played for", Some(format_duration(seconds("playtime")))));
+        lines.push(("State", Some(self.status.get("state").unwrap_or("stop").to_string())));
+        if let Some(audio) = self.status.get("audio") {
+            lines.push(("Format", Some(audio.to_string())));
+        }
+        if let Some(bitrate) = self.status.get("bitrate") {
+            lines.push(("Bitrate", Some(format!("{} kbps", bitrate))));
+        }
+        if let Some(error) = self.status.get("error") {
+            lines.push(("Error", Some(error.to_string())));
+        }
+        lines
+    }
+}
+
+/// Formats seconds as `1d 02:03:04`, leaving out the days if there are none.
+pub fn format_duration(seconds: u64) -> String {
+    let (days, rest) = (seconds / 86400, seconds % 86400);
+    let time = format!("{:02}:{:02}:{:02}", rest / 3600, rest % 3600 / 60, rest % 60);
+    if days > 0 {
+        format!("{}d {}", days, time)
+    } else {
+        time
+    }
+}
@@ -0,0 +1,316 @@
+use std::cell::Cell;
+use std::env;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use portable_pty::{Child, CommandBuilder, MasterPty, PtySize};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use termion::event::Key;
+use tui::Frame;
+use tui::layout::{Alignment, Rect};
+use tui::style::{Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::Paragraph;
+use crate::ansi;
+use crate::config::Config;
+use crate::containers::{F, Pane, Widget};
+use crate::layout::{self, LayoutError};
+use crate::mpd::{MpdClient, MpdError};
+
+/// Gives keys back to the app. This is Ctrl-\, which termion reads as
+/// Ctrl-4.
+const DETACH: Key = Key::Ctrl('4');
+
+/// A shell, or another command, running in a pseudo terminal inside the
+/// pane. Enter attaches the focused pane so every key goes to the terminal,
+/// and Ctrl-\ detaches it again. The terminal is resized to the pane.
+pub struct TerminalWidget {
+    pane: Pane,
+    /// The command to run with `sh -c`, or empty for the user's shell.
+    command: String,
+    /// Whether keys go to the terminal.
+    attached: bool,
+    session: Option<Session>,
+    /// Why the terminal could not be started.
+    error: Option<String>,
+}
+
+impl Widget for TerminalWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+        let session = match &self.session {
+            Some(session) => session,
+            None => {
+                let error = self.error.clone().unwrap_or_default();
+                let message = Paragraph::new(Span::styled(error, style.text_style))
+                    .alignment(Alignment::Center);
+                f.render_widget(message, inner);
+                return;
+            }
+        };
+        session.resize(inner.height, inner.width);
+        let parser = session.parser.lock().unwrap();
+        let screen = parser.screen();
+        let cursor = Some(screen.cursor_position()).filter(|_| self.attached && !screen.hide_cursor());
+        let lines = (0..inner.height)
+            .map(|row| {
+                let mut spans: Vec<Span> = Vec::new();
+                let mut run = String::new();
+                let mut run_style = style.text_style;
+                for col in 0..inner.width {
+                    let cell = match screen.cell(row, col) {
+                        Some(cell) if !cell.is_wide_continuation() => cell,
+                        _ => continue,
+                    };
+                    let mut cell_style = style.text_style.patch(cell_style(cell));
+                    if cursor == Some((row, col)) {
+                        cell_style = cell_style.add_modifier(Modifier::REVERSED);
+                    }
+                    if cell_style != run_style && !run.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut run), run_style));
+                    }
+                    run_style = cell_style;
+                    if cell.has_contents() {
+                        run.push_str(&cell.contents());
+                    } else {
+                        run.push(' ');
+                    }
+                }
+                spans.push(Span::styled(run, run_style));
+                Spans::from(spans)
+            })
+            .collect::<Vec<Spans>>();
+        f.render_widget(Paragraph::new(lines), inner);
+
+        // the hint goes over the bottom border, if there is one
+        let hint = match session.exit_status() {
+            Some(status) => format!(" {}, Enter to restart ", status),
+            None if !self.attached => String::from(" Enter to type here "),
+            None => return,
+        };
+        if inner.bottom() < area.bottom() && area.width > 4 {
+            let width = (hint.chars().count() as u16).min(area.width - 4);
+            let bottom = Rect::new(area.x + 2, area.bottom() - 1, width, 1);
+            f.render_widget(Paragraph::new(Span::styled(hint, style.title_style)), bottom);
+        }
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        let mut value = self.pane.to_value("terminal");
+        value["command"] = json!(self.command);
+        value
+    }
+
+    fn get_text(&self) -> Option<String> {
+        Some(self.command.clone())
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.command = text.trim().to_string();
+        self.start();
+    }
+
+    fn handle_key(&mut self, key: Key, mpd: Option<&mut MpdClient>, config: &Config) -> Result<bool, MpdError> {
+        let running = self.session.as_ref().is_some_and(|session| session.exit_status().is_none());
+        match key {
+            DETACH if self.attached => self.attached = false,
+            key if self.attached => {
+                // terminals send a carriage return for Enter
+                let bytes = match key {
+                    Key::Char('\n') => Some(vec![b'\r']),
+                    key => ansi::key_bytes(key),
+                };
+                if let (Some(session), Some(bytes)) = (&mut self.session, bytes) {
+                    let _ = session.writer.write_all(&bytes).and_then(|_| session.writer.flush());
+                }
+            }
+            Key::Char('\n') if running => self.attached = true,
+            Key::Char('\n') => {
+                self.start();
+                self.attached = self.session.is_some();
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn is_editing(&self) -> bool {
+        self.attached && self.session.as_ref().is_some_and(|session| session.exit_status().is_none())
+    }
+}
+
+impl Default for TerminalWidget {
+    fn default() -> Self {
+        TerminalWidget::new(String::from("Terminal"), String::new())
+    }
+}
+
+impl TerminalWidget {
+    pub fn new(title: String, command: String) -> Self {
+        let mut widget = TerminalWidget {
+            pane: Pane::new(title),
+            command,
+            attached: false,
+            session: None,
+            error: None,
+        };
+        widget.start();
+        widget
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        let mut widget = TerminalWidget::new(String::new(), Self::saved_command(value)?);
+        widget.pane = Pane::from_value(value)?;
+        Ok(widget)
+    }
+
+    pub fn check(value: &Value) -> Result<(), LayoutError> {
+        Self::saved_command(value)?;
+        layout::check_pane(value)
+    }
+
+    fn saved_command(value: &Value) -> Result<String, LayoutError> {
+        Ok(match value.get("command") {
+            Some(command) => String::deserialize(command)?,
+            None => String::new(),
+        })
+    }
+
+    /// Starts the command over in a new terminal.
+    fn start(&mut self) {
+        self.session = None;
+        self.attached = false;
+        match Session::start(&self.command) {
+            Ok(session) => {
+                self.session = Some(session);
+                self.error = None;
+            }
+            Err(err) => self.error = Some(format!("Could not start a terminal: {}", err)),
+        }
+    }
+}
+
+/// A command in a pseudo terminal, with a thread feeding what it prints to
+/// the terminal emulator. Dropping it kills the command.
+struct Session {
+    parser: Arc<Mutex<vt100::Parser>>,
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    child: Mutex<Box<dyn Child + Send + Sync>>,
+    /// Rows and columns the terminal has now.
+    size: Cell<(u16, u16)>,
+}
+
+impl Session {
+    fn start(command: &str) -> Result<Self, String> {
+        // resized to the pane when first drawn
+        let size = (24, 80);
+        let pair = portable_pty::native_pty_system()
+            .openpty(pty_size(size))
+            .map_err(|err| err.to_string())?;
+        let mut builder = if command.is_empty() {
+            CommandBuilder::new_default_prog()
+        } else {
+            let mut builder = CommandBuilder::new("sh");
+            builder.args(["-c", command]);
+            builder
+        };
+        builder.env("TERM", "xterm-256color");
+        if let Ok(dir) = env::current_dir() {
+            builder.cwd(dir);
+        }
+        let child = pair.slave.spawn_command(builder).map_err(|err| err.to_string())?;
+        let mut reader = pair.master.try_clone_reader().map_err(|err| err.to_string())?;
+        let writer = pair.master.take_writer().map_err(|err| err.to_string())?;
+        let parser = Arc::new(Mutex::new(vt100::Parser::new(size.0, size.1, 0)));
+        let feed = parser.clone();
+        thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(read @ 1..) = reader.read(&mut buffer) {
+                feed.lock().unwrap().process(&buffer[..read]);
+            }
+        });
+        Ok(Session {
+            parser,
+            master: pair.master,
+            writer,
+            child: Mutex::new(child),
+            size: Cell::new(size),
+        })
+    }
+
+    /// Resizes the terminal, so the command redraws, if the pane changed
+    /// size.
+    fn resize(&self, rows: u16, cols: u16) {
+        if self.size.get() == (rows, cols) {
+            return;
+        }
+        self.size.set((rows, cols));
+        self.parser.lock().unwrap().set_size(rows, cols);
+        let _ = self.master.resize(pty_size((rows, cols)));
+    }
+
+    /// How the command exited, if it did.
+    fn exit_status(&self) -> Option<String> {
+        match self.child.lock().unwrap().try_wait() {
+            Ok(Some(status)) if status.success() => Some(String::from("Exited")),
+            Ok(Some(status)) => Some(format!("Exited with code {}", status.exit_code())),
+            Ok(None) => None,
+            Err(err) => Some(err.to_string()),
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+fn pty_size((rows, cols): (u16, u16)) -> PtySize {
+    PtySize {
+        rows,
+        cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// The style of a terminal cell, over the pane's text style.
+fn cell_style(cell: &vt100::Cell) -> Style {
+    let color = |color| match color {
+        vt100::Color::Default => None,
+        vt100::Color::Idx(index) => Some(ansi::indexed(index)),
+        vt100::Color::Rgb(r, g, b) => Some(tui::style::Color::Rgb(r, g, b)),
+    };
+    let mut style = Style {
+        fg: color(cell.fgcolor()),
+        bg: color(cell.bgcolor()),
+        ..Style::default()
+    };
+    for (on, modifier) in [
+        (cell.bold(), Modifier::BOLD),
+        (cell.italic(), Modifier::ITALIC),
+        (cell.underline(), Modifier::UNDERLINED),
+        (cell.inverse(), Modifier::REVERSED),
+    ] {
+        if on {
+            style = style.add_modifier(modifier);
+        }
+    }
+    style
+}
@@ -0,0 +1,431 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tui::Frame;
+use tui::layout::Rect;
+use tui::style::Style;
+use tui::symbols::Marker;
+use tui::text::Span;
+use tui::widgets::{BarChart, Paragraph};
+use tui::widgets::canvas::{Canvas, Points};
+use crate::containers::{F, Pane, Widget};
+use crate::layout::{self, LayoutError};
+
+/// Samples per FFT. Also the number of samples kept for the waveform.
+const WINDOW: usize = 2048;
+const MIN_FREQUENCY: f32 = 50.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+/// Levels below this many dB are drawn as empty bars.
+const FLOOR_DB: f32 = -60.0;
+/// How often the fifo is checked while MPD is not writing to it.
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisualizerMode {
+    #[default]
+    Spectrum,
+    Waveform,
+}
+
+/// Settings saved in the layout file. The PCM format must match MPD's fifo
+/// output, e.g. `format "44100:16:2"`; only 16-bit samples are supported.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisualizerOptions {
+    pub fifo: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub mode: VisualizerMode,
+    pub bands: usize,
+    /// How much of the previous frame is kept, from 0 (none) to 1 (frozen).
+    pub smoothing: f32,
+    pub fps: u32,
+}
+
+impl Default for VisualizerOptions {
+    fn default() -> Self {
+        VisualizerOptions {
+            fifo: String::from("/tmp/mpd.fifo"),
+            sample_rate: 44100,
+            channels: 2,
+            mode: VisualizerMode::Spectrum,
+            bands: 32,
+            smoothing: 0.6,
+            fps: 30,
+        }
+    }
+}
+
+/// What the reader thread hands to `draw`.
+#[derive(Default)]
+struct Analysis {
+    /// Band levels from 0 to 1, lowest frequency first.
+    bands: Vec<f32>,
+    /// The most recent samples, from -1 to 1.
+    wave: Vec<f32>,
+    error: Option<String>,
+}
+
+/// Draws a spectrum or waveform of what MPD is playing, read from a fifo
+/// output. Without MPD it can be tried by writing raw PCM to a named pipe:
+///
+/// ```sh
+/// mkfifo /tmp/mpd.fifo
+/// sox -n -r 44100 -b 16 -c 2 -e signed -t raw /tmp/mpd.fifo synth 30 sine 50-10000
+/// ```
+pub struct VisualizerWidget {
+    pane: Pane,
+    options: VisualizerOptions,
+    analysis: Arc<Mutex<Analysis>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Widget for VisualizerWidget {
+    fn draw(&self, f: &mut Frame<F>, area: Rect) {
+        let (inner, style) = self.pane.draw(f, area);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+        let analysis = self.analysis.lock().unwrap();
+        if let Some(error) = &analysis.error {
+            f.render_widget(Paragraph::new(Span::styled(error.clone(), style.text_style)), inner);
+            return;
+        }
+        match self.options.mode {
+            VisualizerMode::Spectrum => {
+                let data = analysis.bands.iter()
+                    .map(|level| ("", (level * 100.0) as u64))
+                    .collect::<Vec<(&str, u64)>>();
+                let bands = data.len().max(1) as u16;
+                let bar_width = (inner.width / bands).saturating_sub(1).max(1);
+                let color = style.text_style.fg.unwrap_or(tui::style::Color::White);
+                let chart = BarChart::default()
+                    .data(&data)
+                    .max(100)
+                    .bar_width(bar_width)
+                    .bar_gap(1)
+                    .bar_style(style.text_style)
+                    // values drawn in the bar's own color are invisible
+                    .value_style(Style::default().fg(color).bg(color));
+                f.render_widget(chart, inner);
+            }
+            VisualizerMode::Waveform => {
+                let samples = inner.width as usize * 2;
+                let start = analysis.wave.len().saturating_sub(samples);
+                let coords = analysis.wave[start..].iter()
+                    .enumerate()
+                    .map(|(x, y)| (x as f64, *y as f64))
+                    .collect::<Vec<(f64, f64)>>();
+                let color = style.text_style.fg.unwrap_or(tui::style::Color::White);
+                let canvas = Canvas::default()
+                    .marker(Marker::Braille)
+                    .x_bounds([0.0, samples as f64])
+                    .y_bounds([-1.0, 1.0])
+                    .paint(|ctx| ctx.draw(&Points { coords: &coords, color }));
+                f.render_widget(canvas, inner);
+            }
+        }
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.pane
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.pane
+    }
+
+    fn to_value(&self) -> Value {
+        let mut value = self.pane.to_value("visualizer");
+        value["options"] = json!(self.options);
+        value
+    }
+}
+
+impl Drop for VisualizerWidget {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Default for VisualizerWidget {
+    fn default() -> Self {
+        VisualizerWidget::new(String::from("Visualizer"), VisualizerOptions::default())
+    }
+}
+
+impl VisualizerWidget {
+    pub fn new(title: String, options: VisualizerOptions) -> Self {
+        let analysis = Arc::new(Mutex::new(Analysis::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        {
+            let options = options.clone();
+            let analysis = analysis.clone();
+            let stop = stop.clone();
+            thread::spawn(move || read_fifo(options, analysis, stop));
+        }
+        VisualizerWidget {
+            pane: Pane::new(title),
+            options,
+            analysis,
+            stop,
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, LayoutError> {
+        let options = Self::options(value)?;
+        let pane = Pane::from_value(value)?;
+        let mut widget = VisualizerWidget::new(pane.title.clone(), options);
+        widget.pane = pane;
+        Ok(widget)
+    }
+
+    pub fn check(value: &Value) -> Result<(), LayoutError> {
+        Self::options(value)?;
+        layout::check_pane(value)
+    }
+
+    fn options(value: &Value) -> Result<VisualizerOptions, LayoutError> {
+        Ok(match value.get("options") {
+            Some(options) => VisualizerOptions::deserialize(options)?,
+            None => VisualizerOptions::default(),
+        })
+    }
+}
+
+/// Reads PCM from the fifo until `stop` is set, publishing a new analysis at
+/// most `fps` times a second. The fifo is read without blocking, so the
+/// thread notices `stop` while MPD is stopped, paused or not running.
+fn read_fifo(options: VisualizerOptions, analysis: Arc<Mutex<Analysis>>, stop: Arc<AtomicBool>) {
+    let frame_time = Duration::from_secs_f32(1.0 / options.fps.max(1) as f32);
+    let channels = options.channels.max(1) as usize;
+    let mut spectrum = Spectrum::new(&options);
+    let mut samples: Vec<f32> = Vec::with_capacity(WINDOW * 2);
+    let mut pending: Vec<u8> = Vec::new();
+    let mut buf = [0u8; 4096];
+    let mut last_frame = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        let mut fifo = match open_fifo(&options.fifo) {
+            Ok(fifo) => fifo,
+            Err(err) => {
+                analysis.lock().unwrap().error = Some(format!("{}: {}", options.fifo, err));
+                sleep_unless(&stop, Duration::from_secs(1));
+                continue;
+            }
+        };
+        analysis.lock().unwrap().error = None;
+        while !stop.load(Ordering::Relaxed) {
+            let read = match fifo.read(&mut buf) {
+                Ok(read) if read > 0 => read,
+                // nothing is writing (0) or nothing has been written yet
+                Ok(_) => {
+                    thread::sleep(POLL);
+                    continue;
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::Interrupted => {
+                    thread::sleep(POLL);
+                    continue;
+                }
+                Err(err) => {
+                    analysis.lock().unwrap().error = Some(format!("{}: {}", options.fifo, err));
+                    break;
+                }
+            };
+            pending.extend_from_slice(&buf[..read]);
+            let frame_bytes = 2 * channels;
+            let frames = pending.len() / frame_bytes;
+            for frame in pending[..frames * frame_bytes].chunks(frame_bytes) {
+                let sum = frame.chunks(2)
+                    .map(|sample| i16::from_le_bytes([sample[0], sample[1]]) as f32)
+                    .sum::<f32>();
+                samples.push(sum / channels as f32 / i16::MAX as f32);
+            }
+            pending.drain(..frames * frame_bytes);
+            if samples.len() > WINDOW {
+                samples.drain(..samples.len() - WINDOW);
+            }
+            if samples.len() == WINDOW && last_frame.elapsed() >= frame_time {
+                last_frame = Instant::now();
+                let bands = spectrum.analyze(&samples);
+                let mut analysis = analysis.lock().unwrap();
+                analysis.bands = bands;
+                analysis.wave = samples.clone();
+            }
+        }
+        // after a read error, before opening the fifo again
+        sleep_unless(&stop, Duration::from_secs(1));
+    }
+}
+
+/// Opens the fifo for reading. Opening a fifo normally blocks until
+/// something opens it for writing; without blocking it opens at once.
+fn open_fifo(path: &str) -> io::Result<File> {
+    OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path)
+}
+
+/// Sleeps for `duration`, waking early once `stop` is set.
+fn sleep_unless(stop: &AtomicBool, duration: Duration) {
+    let start = Instant::now();
+    while !stop.load(Ordering::Relaxed) && start.elapsed() < duration {
+        thread::sleep(POLL.min(duration.saturating_sub(start.elapsed())));
+    }
+}
+
+struct Spectrum {
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    window: Vec<f32>,
+    /// FFT bin ranges for each band.
+    ranges: Vec<(usize, usize)>,
+    smoothing: f32,
+    levels: Vec<f32>,
+}
+
+impl Spectrum {
+    fn new(options: &VisualizerOptions) -> Self {
+        let bands = options.bands.max(1);
+        let nyquist = options.sample_rate as f32 / 2.0;
+        let max = MAX_FREQUENCY.min(nyquist);
+        let bin = |frequency: f32| ((frequency / nyquist) * (WINDOW / 2) as f32) as usize;
+        // bands are spaced logarithmically, like pitch
+        let ranges = (0..bands)
+            .map(|band| {
+                let low = MIN_FREQUENCY * (max / MIN_FREQUENCY).powf(band as f32 / bands as f32);
+                let high = MIN_FREQUENCY * (max / MIN_FREQUENCY).powf((band + 1) as f32 / bands as f32);
+                let low = bin(low).max(1);
+                (low, bin(high).max(low + 1))
+            })
+            .collect();
+        // Hann window
+        let window = (0..WINDOW)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / WINDOW as f32).cos())
+            .collect();
+        Spectrum {
+            fft: FftPlanner::new().plan_fft_forward(WINDOW),
+            window,
+            ranges,
+            smoothing: options.smoothing.clamp(0.0, 1.0),
+            levels: vec![0.0; bands],
+        }
+    }
+
+    fn analyze(&mut self, samples: &[f32]) -> Vec<f32> {
+        let mut buffer = samples.iter()
+            .zip(&self.window)
+            .map(|(sample, window)| Complex::new(sample * window, 0.0))
+            .collect::<Vec<Complex<f32>>>();
+        self.fft.process(&mut buffer);
+        // a full scale sine peaks at WINDOW / 4 with a Hann window
+        let scale = 4.0 / WINDOW as f32;
+        for (level, (low, high)) in self.levels.iter_mut().zip(&self.ranges) {
+            let peak = buffer[*low..*high].iter().map(|c| c.norm()).fold(0.0, f32::max) * scale;
+            let db = 20.0 * peak.max(f32::MIN_POSITIVE).log10();
+            let target = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+            *level = *level * self.smoothing + target * (1.0 - self.smoothing);
+        }
+        self.levels.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::sync::mpsc;
+    use super::*;
+
+    fn sine(frequency: f32, amplitude: f32, sample_rate: u32) -> Vec<f32> {
+        (0..WINDOW)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn sine_peaks_in_its_band() {
+        let options = VisualizerOptions { smoothing: 0.0, ..VisualizerOptions::default() };
+        let nyquist = options.sample_rate as f32 / 2.0;
+        for frequency in [100.0, 1000.0, 5000.0] {
+            let mut spectrum = Spectrum::new(&options);
+            let bands = spectrum.analyze(&sine(frequency, 0.5, options.sample_rate));
+            let peak = (0..bands.len()).max_by(|a, b| bands[*a].total_cmp(&bands[*b])).unwrap();
+            let (low, high) = spectrum.ranges[peak];
+            let hz = |bin: usize| bin as f32 * nyquist / (WINDOW / 2) as f32;
+            // the Hann window spreads a sine over its neighbouring bin
+            assert!(hz(low.saturating_sub(1)) <= frequency && frequency < hz(high + 1), "{} Hz peaked in {}..{} Hz", frequency, hz(low), hz(high));
+            // half of full scale is 6 dB down
+            assert!(bands[peak] > 0.8, "peak level {}", bands[peak]);
+            assert!(bands[0] < 0.5 || peak == 0, "lowest band {}", bands[0]);
+        }
+    }
+
+    #[test]
+    fn silence_is_empty() {
+        let mut spectrum = Spectrum::new(&VisualizerOptions::default());
+        assert!(spectrum.analyze(&[0.0; WINDOW]).iter().all(|level| *level == 0.0));
+    }
+
+    /// A reader on a new fifo, with the fifo's path and a channel that
+    /// receives once the reader returns.
+    fn start_reader(name: &str) -> (std::path::PathBuf, Arc<Mutex<Analysis>>, Arc<AtomicBool>, mpsc::Receiver<()>) {
+        let path = std::env::temp_dir().join(format!("mpd-thing-rs-{}-{}.fifo", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        let options = VisualizerOptions { fifo: path.to_string_lossy().into_owned(), ..VisualizerOptions::default() };
+        let analysis = Arc::new(Mutex::new(Analysis::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (done_tx, done) = mpsc::channel();
+        {
+            let (analysis, stop) = (analysis.clone(), stop.clone());
+            thread::spawn(move || {
+                read_fifo(options, analysis, stop);
+                let _ = done_tx.send(());
+            });
+        }
+        (path, analysis, stop, done)
+    }
+
+    #[test]
+    fn reader_stops_while_nothing_writes() {
+        let (path, analysis, stop, done) = start_reader("idle");
+        thread::sleep(Duration::from_millis(100));
+        stop.store(true, Ordering::Relaxed);
+        let stopped = done.recv_timeout(Duration::from_secs(2));
+        let _ = std::fs::remove_file(&path);
+        assert!(stopped.is_ok(), "reader thread still running");
+        assert!(analysis.lock().unwrap().error.is_none());
+    }
+
+    #[test]
+    fn reader_analyzes_written_pcm() {
+        let (path, analysis, stop, done) = start_reader("pcm");
+        thread::sleep(Duration::from_millis(50));
+        let pcm = sine(1000.0, 0.5, 44100).iter()
+            .flat_map(|sample| {
+                let sample = ((sample * i16::MAX as f32) as i16).to_le_bytes();
+                // both channels
+                [sample, sample].concat()
+            })
+            .collect::<Vec<u8>>();
+        {
+            let mut writer = OpenOptions::new().write(true).open(&path).unwrap();
+            std::io::Write::write_all(&mut writer, &pcm).unwrap();
+            std::io::Write::write_all(&mut writer, &pcm).unwrap();
+        }
+        thread::sleep(Duration::from_millis(200));
+        stop.store(true, Ordering::Relaxed);
+        let stopped = done.recv_timeout(Duration::from_secs(2));
+        let _ = std::fs::remove_file(&path);
+        assert!(stopped.is_ok(), "reader thread still running");
+        let analysis = analysis.lock().unwrap();
+        assert_eq!(analysis.wave.len(), WINDOW);
+        assert!(analysis.bands.iter().any(|level| *level > 0.0));
+    }
+}